@@ -0,0 +1,271 @@
+//! The per-listener access log
+
+use crate::{
+    config::{AccessLogFormat, ServerConfig},
+    error,
+    error::Error,
+    proxy,
+    time::UtcTime,
+};
+use ehttpd::{
+    bytes::{Data, Sink},
+    http::{Request, RequestExt, Response, ResponseExt},
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    str,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+/// The request header fields whose values are never logged
+const SENSITIVE_FIELDS: [&str; 3] = ["Authorization", "Cookie", "Proxy-Authorization"];
+
+/// The placeholder for redacted values
+const REDACTED: &str = "<redacted>";
+
+/// Redacts the webhook name from API targets since the name is the only credential needed to trigger a webhook
+fn redact_target(target: &Data) -> Data {
+    match target.starts_with(b"/api/") {
+        true => Data::from(format!("/api/{REDACTED}")),
+        false => target.clone(),
+    }
+}
+
+/// Redacts the value of credential-bearing header fields
+fn redact_field(key: &Data, value: &Data) -> (Data, Data) {
+    match SENSITIVE_FIELDS.iter().any(|field| key.eq_ignore_ascii_case(field.as_bytes())) {
+        true => (key.clone(), Data::from(REDACTED)),
+        false => (key.clone(), value.clone()),
+    }
+}
+
+/// Gets the peer address of the connection if available
+pub fn peer(sink: &Sink) -> Option<SocketAddr> {
+    match sink {
+        Sink::TcpStream(stream) => stream.peer_addr().ok(),
+        _ => None,
+    }
+}
+
+/// The resources used to handle a single request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    /// The time spent on RCON round trips
+    pub rcon: Duration,
+    /// The metrics label of the invoked webhook, if any
+    pub hook: Option<&'static str>,
+}
+
+/// The size and time budget of a single request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    /// The announced request body size
+    pub request_bytes: u64,
+    /// The response body size
+    pub response_bytes: u64,
+    /// The time spent on RCON round trips
+    pub rcon: Duration,
+    /// The total handler time
+    pub total: Duration,
+}
+impl Budget {
+    /// Adds another budget to this budget
+    pub fn add(&mut self, other: &Self) {
+        self.request_bytes = self.request_bytes.saturating_add(other.request_bytes);
+        self.response_bytes = self.response_bytes.saturating_add(other.response_bytes);
+        self.rcon = self.rcon.saturating_add(other.rcon);
+        self.total = self.total.saturating_add(other.total);
+    }
+}
+
+/// An access log with its format and destination
+#[derive(Debug)]
+pub struct AccessLog {
+    /// The log format
+    format: AccessLogFormat,
+    /// Whether to append the request budget to common and combined log lines
+    budget: bool,
+    /// The file to append to, or `None` to write to stderr
+    file: Option<Mutex<File>>,
+}
+impl AccessLog {
+    /// Opens the access log for the given format and appends to the given file, or writes to stderr if `None`
+    pub fn open(format: AccessLogFormat, budget: bool, path: Option<&str>) -> Result<Self, Error> {
+        let file = match (format, path) {
+            (AccessLogFormat::Off, _) | (_, None) => None,
+            (_, Some(path)) => {
+                let file = OpenOptions::new().create(true).append(true).open(path);
+                let file = file.map_err(|e| error!(with: e, "Failed to open access log {path}"))?;
+                Some(Mutex::new(file))
+            }
+        };
+        Ok(Self { format, budget, file })
+    }
+
+    /// The log format
+    pub const fn format(&self) -> AccessLogFormat {
+        self.format
+    }
+
+    /// Writes a log line at once to avoid interleaving
+    fn write(&self, line: &str) {
+        let Some(file) = &self.file else {
+            eprintln!("{line}");
+            return;
+        };
+
+        // Write the line with a single call since listeners may share the file, and report failures without failing
+        let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = file.write_all(format!("{line}\n").as_bytes()) {
+            eprintln!("Failed to write access log: {e}");
+        }
+    }
+}
+
+/// An access log entry for a single request
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The log format
+    format: AccessLogFormat,
+    /// The client address
+    client: Option<IpAddr>,
+    /// The request method
+    method: Data,
+    /// The request target (with the webhook name redacted)
+    target: Data,
+    /// The request version
+    version: Data,
+    /// The `Referer` and `User-Agent` header fields (only captured for the combined format)
+    agent: Option<(Option<Data>, Option<Data>)>,
+    /// The request header fields with credentials redacted (only captured for the verbose format)
+    fields: Vec<(Data, Data)>,
+    /// The announced request body size
+    body_size: u64,
+}
+impl Entry {
+    /// Captures the request metadata for the given format before the request is consumed by the handler
+    pub fn new(request: &Request, peer: Option<SocketAddr>, server: &ServerConfig, format: AccessLogFormat) -> Self {
+        let client = match format {
+            AccessLogFormat::Off => None,
+            _ => peer.map(|peer| {
+                proxy::client_address(peer.ip(), &request.fields, &server.trusted_proxies, server.forwarded_header)
+            }),
+        };
+        let agent = match format {
+            AccessLogFormat::Combined => {
+                Some((request.field("Referer").cloned(), request.field("User-Agent").cloned()))
+            }
+            _ => None,
+        };
+        let fields = match format {
+            AccessLogFormat::Verbose => request.fields.iter().map(|(key, value)| redact_field(key, value)).collect(),
+            _ => Vec::new(),
+        };
+        let body_size = request.content_length().ok().flatten().unwrap_or(0);
+        Self {
+            format,
+            client,
+            method: request.method.clone(),
+            target: redact_target(&request.target),
+            version: request.version.clone(),
+            agent,
+            fields,
+            body_size,
+        }
+    }
+
+    /// Computes the budget of the request from the response, the resource usage and the total handler time
+    pub fn budget(&self, response: &Response, usage: &Usage, total: Duration) -> Budget {
+        let response_bytes = response.content_length().ok().flatten().unwrap_or(0);
+        Budget { request_bytes: self.body_size, response_bytes, rcon: usage.rcon, total }
+    }
+
+    /// Writes the log entry to the given access log
+    pub fn log(&self, access_log: &AccessLog, response: &Response, budget: &Budget) {
+        /// Converts data to a string for logging
+        fn lossy(data: &Data) -> &str {
+            str::from_utf8(data).unwrap_or("<non UTF-8>")
+        }
+
+        // Don't format anything if logging is disabled
+        if self.format == AccessLogFormat::Off {
+            return;
+        }
+
+        // Format the common log line
+        let peer = self.client.map(|client| client.to_string()).unwrap_or_else(|| "-".to_string());
+        let (method, target, version) = (lossy(&self.method), lossy(&self.target), lossy(&self.version));
+        let status = lossy(&response.status);
+        let size = budget.response_bytes;
+        let mut line = format!("{peer} - - [{}] \"{method} {target} {version}\" {status} {size}", UtcTime::now());
+
+        // Append the referer and user agent if combined
+        if let Some((referer, user_agent)) = &self.agent {
+            let referer = referer.as_ref().map(lossy).unwrap_or("-");
+            let user_agent = user_agent.as_ref().map(lossy).unwrap_or("-");
+            line.push_str(&format!(" \"{}\" \"{}\"", referer.escape_default(), user_agent.escape_default()));
+        }
+
+        // Append the request budget if enabled or verbose
+        if access_log.budget || self.format == AccessLogFormat::Verbose {
+            let Budget { request_bytes, response_bytes, rcon, total } = budget;
+            let (rcon, total) = (rcon.as_millis(), total.as_millis());
+            line.push_str(&format!(
+                " request_bytes={request_bytes} response_bytes={response_bytes} rcon_ms={rcon} total_ms={total}"
+            ));
+        }
+
+        // Append all request header fields if verbose
+        if self.format == AccessLogFormat::Verbose {
+            for (key, value) in &self.fields {
+                line.push_str(&format!("\n    {}: {}", lossy(key), lossy(value)));
+            }
+        }
+
+        // Write the log line
+        access_log.write(&line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact_field, redact_target, Budget};
+    use ehttpd::bytes::Data;
+    use std::time::Duration;
+
+    #[test]
+    fn redacts_webhook_names() {
+        assert_eq!(&*redact_target(&Data::from("/api/secret-hook-name")), b"/api/<redacted>");
+        assert_eq!(&*redact_target(&Data::from("/api/")), b"/api/<redacted>");
+        assert_eq!(&*redact_target(&Data::from("/dashboard")), b"/dashboard");
+        assert_eq!(&*redact_target(&Data::from("/apiary")), b"/apiary");
+    }
+
+    #[test]
+    fn redacts_credentials() {
+        for key in ["Authorization", "cookie", "PROXY-AUTHORIZATION"] {
+            let (_, value) = redact_field(&Data::from(key), &Data::from("Basic dXNlcjpwYXNz"));
+            assert_eq!(&*value, b"<redacted>");
+        }
+        let (key, value) = redact_field(&Data::from("User-Agent"), &Data::from("curl/8.0"));
+        assert_eq!((&*key, &*value), (&b"User-Agent"[..], &b"curl/8.0"[..]));
+    }
+
+    #[test]
+    fn budget_add_saturates() {
+        let mut budget = Budget { request_bytes: 1, response_bytes: u64::MAX, ..Default::default() };
+        let other = Budget {
+            request_bytes: 2,
+            response_bytes: 1,
+            rcon: Duration::from_millis(3),
+            total: Duration::from_millis(4),
+        };
+        budget.add(&other);
+        budget.add(&other);
+        assert_eq!((budget.request_bytes, budget.response_bytes), (5, u64::MAX));
+        assert_eq!((budget.rcon, budget.total), (Duration::from_millis(6), Duration::from_millis(8)));
+    }
+}
@@ -0,0 +1,194 @@
+//! A size-bounded map with time-based expiry
+
+use crate::config::CacheConfig;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// A cache entry
+#[derive(Debug)]
+struct Entry<V> {
+    /// The cached value
+    value: V,
+    /// The point in time when the entry expires
+    expires: Instant,
+    /// The logical timestamp of the last access to determine the least recently used entry
+    accessed: u64,
+}
+
+/// A size-bounded least-recently-used map where entries expire after a fixed time-to-live
+///
+/// # Note
+/// Expiry is lazy; i.e. expired entries are dropped on access or whenever the map would need to evict an entry to
+/// make room for a new one. The cache is not synchronized, so it should be wrapped into a `Mutex` if shared.
+#[derive(Debug)]
+pub struct Cache<K, V> {
+    /// The cache entries
+    entries: BTreeMap<K, Entry<V>>,
+    /// The maximum amount of entries
+    entries_max: usize,
+    /// The time-to-live of an entry
+    ttl: Duration,
+    /// The logical clock to track the access order
+    clock: u64,
+}
+impl<K, V> Cache<K, V>
+where
+    K: Ord + Clone,
+{
+    /// Creates a new cache with the configured size limit and the given time-to-live for each entry
+    pub const fn new(config: &CacheConfig, ttl: Duration) -> Self {
+        Self { entries: BTreeMap::new(), entries_max: config.entries_max, ttl, clock: 0 }
+    }
+
+    /// Gets the value for the given key if it exists and has not expired yet
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        // Drop the entry if it has expired
+        let now = Instant::now();
+        if self.entries.get(key).is_some_and(|entry| entry.expires <= now) {
+            self.entries.remove(key);
+        }
+
+        // Mark the entry as accessed
+        let accessed = self.tick();
+        let entry = self.entries.get_mut(key)?;
+        entry.accessed = accessed;
+        Some(&entry.value)
+    }
+
+    /// Inserts a value for the given key, evicting the least recently used entry if the cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        // Make room for the new entry if necessary
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.entries_max {
+            self.prune();
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.entries_max {
+            self.evict();
+        }
+
+        // Don't insert anything if the cache cannot hold any entries at all
+        if self.entries_max == 0 {
+            return;
+        }
+
+        // Insert the entry
+        let expires = Instant::now().checked_add(self.ttl).unwrap_or_else(Instant::now);
+        let accessed = self.tick();
+        self.entries.insert(key, Entry { value, expires, accessed });
+    }
+
+    /// Drops all expired entries
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.expires > now);
+    }
+
+    /// Evicts the least recently used entry
+    fn evict(&mut self) {
+        // Find the least recently used entry
+        let lru = self.entries.iter().min_by_key(|(_, entry)| entry.accessed).map(|(key, _)| key.clone());
+        if let Some(key) = lru {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Advances the logical clock and returns the new timestamp
+    fn tick(&mut self) -> u64 {
+        self.clock = self.clock.wrapping_add(1);
+        self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+    use crate::config::CacheConfig;
+    use std::{thread, time::Duration};
+
+    /// Creates a cache with the given limit and time-to-live
+    fn cache(entries_max: usize, ttl: Duration) -> Cache<u32, &'static str> {
+        Cache::new(&CacheConfig { entries_max }, ttl)
+    }
+
+    #[test]
+    fn get_insert() {
+        let mut cache = cache(4, Duration::from_secs(60));
+        assert_eq!(cache.get(&1), None);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&2), Some(&"two"));
+
+        // Overwrite an existing entry
+        cache.insert(1, "uno");
+        assert_eq!(cache.get(&1), Some(&"uno"));
+    }
+
+    #[test]
+    fn eviction_order() {
+        let mut cache = cache(2, Duration::from_secs(60));
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+
+        // Access the first entry, so that the second one is the least recently used
+        assert_eq!(cache.get(&1), Some(&"one"));
+        cache.insert(3, "three");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&3), Some(&"three"));
+
+        // Overwriting an existing key does not evict anything
+        cache.insert(3, "drei");
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&3), Some(&"drei"));
+    }
+
+    #[test]
+    fn lazy_expiry() {
+        let mut cache = cache(2, Duration::from_millis(50));
+        cache.insert(1, "one");
+        assert_eq!(cache.get(&1), Some(&"one"));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn expired_entries_are_pruned_before_eviction() {
+        let mut cache = cache(2, Duration::from_millis(50));
+        cache.insert(1, "one");
+        thread::sleep(Duration::from_millis(100));
+
+        // Inserting into a full cache prunes the expired entry, so nothing else needs to be evicted
+        cache.insert(2, "two");
+        cache.insert(3, "three");
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get(&2), Some(&"two"));
+        assert_eq!(cache.get(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn prune() {
+        let mut cache = cache(4, Duration::from_millis(50));
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        thread::sleep(Duration::from_millis(100));
+        cache.prune();
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn zero_entries() {
+        let mut cache = cache(0, Duration::from_secs(60));
+        cache.insert(1, "one");
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn zero_ttl() {
+        let mut cache = cache(4, Duration::ZERO);
+        cache.insert(1, "one");
+        assert_eq!(cache.get(&1), None);
+    }
+}
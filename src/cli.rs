@@ -0,0 +1,74 @@
+//! The command line arguments
+
+use crate::{
+    config::Config,
+    error,
+    error::{Error, ErrorKind},
+};
+use std::env;
+
+/// The command line arguments
+///
+/// # Precedence
+/// Config values passed as flags take precedence over the environment, which takes precedence over the config file.
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    /// Whether to only validate the configured webhooks instead of starting the server
+    pub validate_hooks: bool,
+    /// The path to the config file (takes precedence over `CONFIG_FILE`)
+    pub config: Option<String>,
+    /// The address to listen on (overrides `server.address`)
+    pub listen: Option<String>,
+    /// The RCON address (overrides `rcon.address`)
+    pub rcon_address: Option<String>,
+}
+impl Args {
+    /// Parses the command line arguments
+    pub fn parse() -> Result<Self, Error> {
+        let mut this = Self::default();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            // Split `--flag=value` arguments
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+
+            // Get the value of the flag, either inline or from the next argument
+            let mut value = || match inline_value.clone().or_else(|| args.next()) {
+                Some(value) => Ok(value),
+                None => Err(error!("Missing value for command line argument: {flag}").with_kind(ErrorKind::Usage)),
+            };
+
+            // Parse the flag
+            match flag {
+                "--validate-hooks" if inline_value.is_none() => this.validate_hooks = true,
+                "--config" => this.config = Some(value()?),
+                "--listen" => this.listen = Some(value()?),
+                "--rcon-address" => this.rcon_address = Some(value()?),
+                _ => return Err(error!("Invalid command line argument: {arg}").with_kind(ErrorKind::Usage)),
+            }
+        }
+        Ok(this)
+    }
+
+    /// Applies the config overrides from the environment (`SERVER_ADDRESS`, `RCON_ADDRESS`) and the flags to the given
+    /// config
+    pub fn apply(&self, config: &mut Config) {
+        // Apply the environment
+        if let Ok(address) = env::var("SERVER_ADDRESS") {
+            config.server.address = address;
+        }
+        if let Ok(address) = env::var("RCON_ADDRESS") {
+            config.rcon.address = address;
+        }
+
+        // Apply the flags
+        if let Some(listen) = &self.listen {
+            config.server.address = listen.clone();
+        }
+        if let Some(rcon_address) = &self.rcon_address {
+            config.rcon.address = rcon_address.clone();
+        }
+    }
+}
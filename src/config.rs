@@ -1,6 +1,13 @@
 //! The URL database
 
-use crate::error::Error;
+use crate::{
+    error,
+    error::{Error, ErrorKind},
+    pattern::Pattern,
+    proxy::Cidr,
+    schedule::CronExpr,
+    state,
+};
 use serde::Deserialize;
 use std::{borrow::Cow, collections::BTreeMap, env, ops::Deref};
 
@@ -12,6 +19,30 @@ pub struct ServerConfig {
     /// The connection hart limit; i.e. the amount of threads to spawn at max to process incoming connections
     #[serde(default = "ServerConfig::connection_limit_default")]
     pub connection_limit: usize,
+    /// Whether to expose the per-hook invocation and error counters via `X-Hook-Count`/`X-Hook-Errors` header fields
+    #[serde(default)]
+    pub hook_counters: bool,
+    /// The access log format for this listener (defaults to the global access log format)
+    #[serde(default)]
+    pub access_log: Option<AccessLogFormat>,
+    /// The file to append the access log of this listener to (defaults to the global access log file)
+    #[serde(default)]
+    pub access_log_file: Option<String>,
+    /// The trusted reverse proxies whose forwarded header fields are honored to determine the client address
+    #[serde(default)]
+    pub trusted_proxies: Vec<Cidr>,
+    /// The forwarded header field that is honored for trusted proxies
+    #[serde(default)]
+    pub forwarded_header: ForwardedHeader,
+    /// Whether to trim leading and trailing whitespace from RCON responses by default
+    #[serde(default)]
+    pub trim_response: bool,
+    /// Whether to collapse runs of blank lines in RCON responses into a single blank line by default
+    #[serde(default)]
+    pub trim_collapse_newlines: bool,
+    /// The amount of seconds to announce via `Retry-After` for `429` and `503` responses
+    #[serde(default)]
+    pub retry_after: Option<u64>,
 }
 impl ServerConfig {
     /// The default value for the connection hard limit
@@ -20,6 +51,46 @@ impl ServerConfig {
     }
 }
 
+/// An access log format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// No access logging
+    #[default]
+    Off,
+    /// The common log format
+    Common,
+    /// The combined log format, i.e. the common log format with the `Referer` and `User-Agent` header fields
+    Combined,
+    /// The common log format with the request budget and all request header fields
+    Verbose,
+}
+
+/// The header field that trusted proxies use to forward the client address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForwardedHeader {
+    /// The standardized `Forwarded` header field (RFC 7239)
+    Forwarded,
+    /// The de-facto standard `X-Forwarded-For` header field
+    #[default]
+    XForwardedFor,
+}
+
+/// The global logging config
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogConfig {
+    /// The default access log format for all listeners
+    #[serde(default)]
+    pub access: AccessLogFormat,
+    /// Whether to append the per-request budget to `common` and `combined` log lines (always included in `verbose`)
+    #[serde(default)]
+    pub budget: bool,
+    /// The default file to append the access log to, or `None` to write it to stderr
+    #[serde(default)]
+    pub access_file: Option<String>,
+}
+
 /// The Minecraft server RCON config
 #[derive(Debug, Clone, Deserialize)]
 pub struct RconConfig {
@@ -27,14 +98,249 @@ pub struct RconConfig {
     pub address: String,
     /// The RCON password
     pub password: Option<String>,
+    /// The maximum size of a single RCON response packet
+    #[serde(default = "RconConfig::packet_size_max_default")]
+    pub packet_size_max: u32,
+}
+impl RconConfig {
+    /// The minimum packet size, i.e. the size of an empty packet with request ID, type and both null terminators
+    pub const PACKET_SIZE_MIN: u32 = 4 + 4 + 2;
+
+    /// The default value for the maximum packet size as used by vanilla servers (see
+    /// <https://wiki.vg/Rcon#Fragmentation>)
+    const fn packet_size_max_default() -> u32 {
+        4110
+    }
+}
+
+/// The config for the size-bounded in-memory maps
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheConfig {
+    /// The maximum amount of entries per map
+    #[serde(default = "CacheConfig::entries_max_default")]
+    pub entries_max: usize,
+}
+impl CacheConfig {
+    /// The default value for the maximum amount of entries per map
+    const fn entries_max_default() -> usize {
+        4096
+    }
+}
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { entries_max: Self::entries_max_default() }
+    }
+}
+
+/// A webhook definition
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    /// The RCON command to execute
+    pub command: String,
+    /// An optional guard command that is executed first to decide whether `command` should be executed
+    #[serde(default)]
+    pub guard_command: Option<String>,
+    /// The pattern the guard command output must match for `command` to be executed
+    #[serde(default)]
+    pub guard_glob: Option<Pattern>,
+    /// The HTTP status code to respond with if the guard does not match
+    #[serde(default = "WebhookConfig::guard_status_default")]
+    pub guard_status: u16,
+    /// The response body if the guard does not match
+    #[serde(default)]
+    pub guard_message: String,
+    /// The patterns to extract the result count from the command output (uses the vanilla phrasings if empty)
+    #[serde(default)]
+    pub result_count_glob: Vec<Pattern>,
+    /// The state values to set after the command has been executed successfully
+    #[serde(default)]
+    pub set_state: BTreeMap<String, String>,
+    /// Whether to trim leading and trailing whitespace from the RCON response (defaults to `server.trim_response`)
+    #[serde(default)]
+    pub trim_response: Option<bool>,
+    /// Whether to collapse runs of blank lines into a single blank line (defaults to `server.trim_collapse_newlines`)
+    #[serde(default)]
+    pub trim_collapse_newlines: Option<bool>,
+    /// The label to export the counters with (the hook name must not be used since it is the webhook secret)
+    #[serde(default)]
+    pub metrics_label: Option<String>,
+}
+impl WebhookConfig {
+    /// The default value for the status code if the guard does not match
+    const fn guard_status_default() -> u16 {
+        412
+    }
+}
+
+/// A webhook entry, which is either a plain command or a full definition
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum WebhookEntry {
+    /// A plain RCON command
+    Command(String),
+    /// A full webhook definition
+    Webhook(WebhookConfig),
+}
+impl From<WebhookEntry> for WebhookConfig {
+    fn from(entry: WebhookEntry) -> Self {
+        match entry {
+            WebhookEntry::Webhook(webhook) => webhook,
+            WebhookEntry::Command(command) => Self {
+                command,
+                guard_command: None,
+                guard_glob: None,
+                guard_status: Self::guard_status_default(),
+                guard_message: String::new(),
+                result_count_glob: Vec::new(),
+                set_state: BTreeMap::new(),
+                trim_response: None,
+                trim_collapse_newlines: None,
+                metrics_label: None,
+            },
+        }
+    }
 }
 
 /// The webhook database
 #[derive(Debug, Clone, Deserialize)]
-#[serde(transparent)]
+#[serde(from = "BTreeMap<String, WebhookEntry>")]
 pub struct WebhookDatabase {
     /// The predefined webhooks
-    pub hooks: BTreeMap<String, String>,
+    pub hooks: BTreeMap<String, WebhookConfig>,
+}
+impl From<BTreeMap<String, WebhookEntry>> for WebhookDatabase {
+    fn from(entries: BTreeMap<String, WebhookEntry>) -> Self {
+        let hooks = entries.into_iter().map(|(name, entry)| (name, entry.into())).collect();
+        Self { hooks }
+    }
+}
+
+/// The admin dashboard config
+#[derive(Debug, Clone, Deserialize)]
+pub struct DashboardConfig {
+    /// The bearer token required to access the dashboard
+    pub token: String,
+    /// The status command to execute against the RCON server
+    #[serde(default = "DashboardConfig::command_default")]
+    pub command: String,
+    /// The time in seconds to cache the status
+    #[serde(default = "DashboardConfig::cache_ttl_default")]
+    pub cache_ttl: u64,
+}
+impl DashboardConfig {
+    /// The default value for the status command
+    fn command_default() -> String {
+        "list".to_string()
+    }
+    /// The default value for the status cache time-to-live
+    const fn cache_ttl_default() -> u64 {
+        10
+    }
+}
+
+/// The persisted state store config
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateConfig {
+    /// The path to the JSON state file
+    pub file: String,
+    /// The maximum amount of entries
+    #[serde(default = "StateConfig::entries_max_default")]
+    pub entries_max: usize,
+    /// The maximum size of a single value in bytes
+    #[serde(default = "StateConfig::value_size_max_default")]
+    pub value_size_max: usize,
+}
+impl StateConfig {
+    /// The default value for the maximum amount of entries
+    const fn entries_max_default() -> usize {
+        256
+    }
+    /// The default value for the maximum size of a single value
+    const fn value_size_max_default() -> usize {
+        1024
+    }
+}
+
+/// The Prometheus metrics endpoint config
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetricsConfig {
+    /// A dedicated address to serve the metrics on (e.g. `127.0.0.1:9100`), or `None` to serve them on the main server
+    #[serde(default)]
+    pub address: Option<String>,
+    /// The access log format for the dedicated metrics listener (defaults to `log.access`)
+    #[serde(default)]
+    pub access_log: Option<AccessLogFormat>,
+    /// The file to append the access log of the dedicated metrics listener to (defaults to `log.access_file`)
+    #[serde(default)]
+    pub access_log_file: Option<String>,
+}
+
+/// How the health signals are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthMode {
+    /// Healthy if all signals are healthy
+    #[default]
+    All,
+    /// Healthy if any signal is healthy
+    Any,
+}
+
+/// A named health signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthSignal {
+    /// The RCON server is reachable and accepts the probe command
+    RconReachable,
+    /// The round-trip time of the probe command is within `latency_max`
+    RconLatency,
+}
+
+/// The readiness endpoint config
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthConfig {
+    /// How the signals are combined
+    #[serde(default)]
+    pub mode: HealthMode,
+    /// The signals to evaluate
+    #[serde(default = "HealthConfig::signals_default")]
+    pub signals: Vec<HealthSignal>,
+    /// The probe command
+    #[serde(default = "HealthConfig::command_default")]
+    pub command: String,
+    /// The maximum round-trip time of the probe command in milliseconds
+    #[serde(default = "HealthConfig::latency_max_default")]
+    pub latency_max: u64,
+    /// The time in seconds to cache the probe result
+    #[serde(default = "HealthConfig::cache_ttl_default")]
+    pub cache_ttl: u64,
+}
+impl HealthConfig {
+    /// The default value for the signals
+    fn signals_default() -> Vec<HealthSignal> {
+        vec![HealthSignal::RconReachable]
+    }
+    /// The default value for the probe command
+    fn command_default() -> String {
+        "list".to_string()
+    }
+    /// The default value for the maximum round-trip time
+    const fn latency_max_default() -> u64 {
+        500
+    }
+    /// The default value for the time to cache the probe result
+    const fn cache_ttl_default() -> u64 {
+        10
+    }
+}
+
+/// A scheduled webhook execution
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleConfig {
+    /// The cron expression (evaluated in UTC)
+    pub cron: CronExpr,
+    /// The name of the webhook to execute
+    pub hook: String,
 }
 
 /// The URL database
@@ -46,19 +352,91 @@ pub struct Config {
     pub rcon: RconConfig,
     /// The webhook database
     pub webhooks: WebhookDatabase,
+    /// The config for the size-bounded in-memory maps
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// The global logging config
+    #[serde(default)]
+    pub log: LogConfig,
+    /// The scheduled webhook executions
+    #[serde(default)]
+    pub schedules: Vec<ScheduleConfig>,
+    /// The optional admin dashboard
+    #[serde(default)]
+    pub dashboard: Option<DashboardConfig>,
+    /// The optional persisted state store
+    #[serde(default)]
+    pub state: Option<StateConfig>,
+    /// The optional Prometheus metrics endpoint
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+    /// The optional readiness endpoint
+    #[serde(default)]
+    pub health: Option<HealthConfig>,
 }
 impl Config {
-    /// Loads the config from the file
-    pub fn load() -> Result<Self, Error> {
-        // Get the path from the environment or fallback to a default path
-        let path = match env::var("CONFIG_FILE") {
-            Ok(path) => Cow::Owned(path),
-            Err(_) => Cow::Borrowed("config.toml"),
+    /// Loads the config from the given file or, if unset, from the file specified by the environment
+    ///
+    /// # Note
+    /// The config is not validated yet, so that overrides can be applied before validation.
+    pub fn load(path: Option<&str>) -> Result<Self, Error> {
+        // Get the path from the argument or the environment or fallback to a default path
+        let path = match (path, env::var("CONFIG_FILE")) {
+            (Some(path), _) => Cow::Borrowed(path),
+            (None, Ok(path)) => Cow::Owned(path),
+            (None, Err(_)) => Cow::Borrowed("config.toml"),
         };
 
         // Decode the database
-        let data = std::fs::read_to_string(path.deref())?;
+        let data = std::fs::read_to_string(path.deref())
+            .map_err(|e| error!(with: e, "Failed to read config file: {path}").with_kind(ErrorKind::Config))?;
         let config: Self = toml::from_str(&data)?;
         Ok(config)
     }
+
+    /// Collects the webhook definitions that would fail at runtime, together with the problem for each hook
+    pub fn webhook_problems(&self) -> Vec<(&str, String)> {
+        let mut problems = Vec::new();
+        for (name, webhook) in &self.webhooks.hooks {
+            // Validate the guard
+            if webhook.guard_command.is_some() != webhook.guard_glob.is_some() {
+                problems.push((name.as_str(), "guard_command and guard_glob must be set together".to_string()));
+            }
+            let status = webhook.guard_status;
+            if !(200..=599).contains(&status) || matches!(status, 204 | 205 | 304) {
+                let problem = format!("guard_status {status} is invalid (must be 200..=599 except 204, 205 and 304)");
+                problems.push((name.as_str(), problem));
+            }
+
+            // Validate the state references
+            let templates = [Some(&webhook.command), webhook.guard_command.as_ref()].into_iter().flatten();
+            let stateful = templates.chain(webhook.set_state.values()).any(|template| state::is_stateful(template));
+            if (stateful || !webhook.set_state.is_empty()) && self.state.is_none() {
+                problems.push((name.as_str(), "webhook uses state, but the state store is disabled".to_string()));
+            }
+        }
+        problems
+    }
+
+    /// Validates the config values that cannot be expressed by the types
+    pub fn validate(&self) -> Result<(), Error> {
+        // Validate the RCON packet size
+        let packet_size_max = self.rcon.packet_size_max;
+        if packet_size_max < RconConfig::PACKET_SIZE_MIN || i32::try_from(packet_size_max).is_err() {
+            let (min, max) = (RconConfig::PACKET_SIZE_MIN, i32::MAX);
+            let error = error!("Invalid RCON packet size limit: {packet_size_max} (must be within {min}..={max})");
+            return Err(error.with_kind(ErrorKind::Config));
+        }
+
+        // Validate the webhooks
+        if let Some((name, problem)) = self.webhook_problems().into_iter().next() {
+            return Err(error!("Invalid webhook {name}: {problem}").with_kind(ErrorKind::Config));
+        }
+
+        // Validate the health definition
+        if self.health.as_ref().is_some_and(|health| health.signals.is_empty()) {
+            return Err(error!("Invalid health definition: no signals configured").with_kind(ErrorKind::Config));
+        }
+        Ok(())
+    }
 }
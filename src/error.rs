@@ -23,9 +23,41 @@ macro_rules! error {
     }};
 }
 
+/// The error category
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorKind {
+    /// Invalid command line arguments
+    Usage,
+    /// An invalid or unreadable config
+    Config,
+    /// An I/O or network error which may be transient
+    Io,
+    /// The upstream server could not be reached, i.e. a transient network error before any data was sent
+    Unavailable,
+    /// The listening address is already in use
+    AddrInUse,
+    /// Any other error
+    #[default]
+    Other,
+}
+impl ErrorKind {
+    /// The process exit code for fatal errors of this kind (see `sysexits.h`)
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::Usage => 64,
+            Self::Config => 78,
+            Self::Io | Self::Unavailable => 75,
+            Self::AddrInUse => 69,
+            Self::Other => 1,
+        }
+    }
+}
+
 /// The crates error type
 #[derive(Debug)]
 pub struct Error {
+    /// The error category
+    pub kind: ErrorKind,
     /// The error description
     pub error: String,
     /// The underlying error
@@ -38,7 +70,13 @@ impl Error {
     #[doc(hidden)]
     pub fn new(error: String, source: Option<Box<dyn error::Error + Send>>) -> Self {
         let backtrace = Backtrace::capture();
-        Self { error, source, backtrace }
+        Self { kind: ErrorKind::default(), error, source, backtrace }
+    }
+
+    /// Sets the error category
+    pub const fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
     }
 
     /// Whether the error has captured a backtrace or not
@@ -78,16 +116,16 @@ impl From<TryFromIntError> for Error {
 }
 impl From<std::io::Error> for Error {
     fn from(source: std::io::Error) -> Self {
-        error!(with: source, "I/O error")
+        error!(with: source, "I/O error").with_kind(ErrorKind::Io)
     }
 }
 impl From<toml::de::Error> for Error {
     fn from(source: toml::de::Error) -> Self {
-        error!(with: source, "TOML decoding error")
+        error!(with: source, "TOML decoding error").with_kind(ErrorKind::Config)
     }
 }
 impl From<ehttpd::error::Error> for Error {
     fn from(source: ehttpd::error::Error) -> Self {
-        error!(with: source, "ehttpd decoding error")
+        error!(with: source, "ehttpd decoding error").with_kind(ErrorKind::Io)
     }
 }
@@ -0,0 +1,126 @@
+//! The readiness endpoint evaluating the configured health definition
+
+use crate::{
+    accesslog::Usage,
+    cache::Cache,
+    config::{Config, HealthConfig, HealthMode, HealthSignal},
+    minecraft, state,
+};
+use ehttpd::http::{Response, ResponseExt};
+use std::{
+    fmt::Write as _,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The result of an RCON probe
+#[derive(Debug, Clone, Copy)]
+struct Probe {
+    /// Whether the RCON server is reachable and accepts the probe command
+    reachable: bool,
+    /// The round-trip time of the probe command
+    latency: Duration,
+}
+
+/// The evaluation of a single signal
+#[derive(Debug, Clone)]
+struct Evaluation {
+    /// The signal name
+    name: &'static str,
+    /// Whether the signal is healthy
+    healthy: bool,
+    /// A human readable detail
+    detail: String,
+}
+
+/// Gets the probe result, either from cache or by executing the probe command
+///
+/// # Note
+/// The cache is locked during the probe, so that concurrent requests don't open concurrent RCON connections.
+fn probe(config: &Config, health: &HealthConfig, usage: &mut Usage) -> Probe {
+    /// The cached probe results
+    static CACHE: OnceLock<Mutex<Cache<String, Probe>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| {
+        // Create the cache
+        let ttl = Duration::from_secs(health.cache_ttl);
+        Mutex::new(Cache::new(&config.cache, ttl))
+    });
+
+    // Use the cached probe result if possible
+    let target = &config.rcon.address;
+    let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(probe) = cache.get(target) {
+        return *probe;
+    }
+
+    // Execute the probe command and log the error if any
+    let start = Instant::now();
+    let result = minecraft::exec(config, &health.command);
+    let probe = Probe { reachable: result.is_ok(), latency: start.elapsed() };
+    usage.rcon = usage.rcon.saturating_add(probe.latency);
+    if let Err(e) = result {
+        eprintln!("Readiness probe failed: {e}");
+    }
+
+    // Cache the probe result
+    cache.insert(target.clone(), probe);
+    probe
+}
+
+/// Evaluates a single signal against the given probe result
+fn evaluate(signal: HealthSignal, probe: Probe, latency_max: u64) -> Evaluation {
+    let latency_ms = probe.latency.as_millis();
+    match (signal, probe.reachable) {
+        (HealthSignal::RconReachable, reachable) => {
+            let detail = if reachable { "reachable" } else { "unreachable" };
+            Evaluation { name: "rcon_reachable", healthy: reachable, detail: detail.to_string() }
+        }
+        (HealthSignal::RconLatency, true) => Evaluation {
+            name: "rcon_latency",
+            healthy: latency_ms <= u128::from(latency_max),
+            detail: format!("{latency_ms}ms (limit {latency_max}ms)"),
+        },
+        (HealthSignal::RconLatency, false) => {
+            Evaluation { name: "rcon_latency", healthy: false, detail: "unreachable".to_string() }
+        }
+    }
+}
+
+/// Serves the readiness status
+pub fn ready(config: &Config, usage: &mut Usage) -> Response {
+    // Ensure the readiness endpoint is enabled
+    let Some(health) = &config.health else {
+        // Return 404
+        let mut response: Response = ResponseExt::new_404_notfound();
+        response.set_content_length(0);
+        return response;
+    };
+
+    // Evaluate and combine the signals
+    let probe = probe(config, health, usage);
+    let evaluations: Vec<_> =
+        (health.signals.iter()).map(|signal| evaluate(*signal, probe, health.latency_max)).collect();
+    let (healthy, mode) = match health.mode {
+        HealthMode::All => (evaluations.iter().all(|evaluation| evaluation.healthy), "all"),
+        HealthMode::Any => (evaluations.iter().any(|evaluation| evaluation.healthy), "any"),
+    };
+
+    // Render the breakdown
+    let mut json = format!("{{\"healthy\": {healthy}, \"mode\": \"{mode}\", \"signals\": [");
+    for (index, evaluation) in evaluations.iter().enumerate() {
+        json.push_str(if index == 0 { "\n    " } else { ",\n    " });
+        let _ = write!(json, "{{\"name\": \"{}\", \"healthy\": {}, \"detail\": ", evaluation.name, evaluation.healthy);
+        state::json_string(&evaluation.detail, &mut json);
+        json.push('}');
+    }
+    json.push_str("\n]}\n");
+
+    // Create 200 OK or 503 response
+    let mut response: Response = match healthy {
+        true => ResponseExt::new_200_ok(),
+        false => ResponseExt::new_status_reason(503, "Service Unavailable"),
+    };
+    response.set_field("Content-Type", "application/json");
+    response.set_body_data(json);
+    response
+}
@@ -11,69 +11,200 @@
 #![warn(clippy::allow_attributes_without_reason)]
 #![warn(clippy::cognitive_complexity)]
 
+mod accesslog;
+mod cache;
+mod cli;
 mod config;
 mod error;
+mod health;
+mod metrics;
 mod minecraft;
+mod pattern;
+mod proxy;
+mod schedule;
+mod state;
+mod time;
 mod webui;
 
-use crate::{config::Config, error::Error};
+use crate::{
+    accesslog::{AccessLog, Entry, Usage},
+    cli::Args,
+    config::Config,
+    error::{Error, ErrorKind},
+};
 use ehttpd::{
     http::{Request, Response, ResponseExt},
     Server,
 };
-use std::{process, str, sync::Arc};
+use std::{convert::Infallible, io, process, str, sync::Arc, thread, time::Instant};
+
+/// A request router
+type Router = fn(Request, &Arc<Config>, &mut Usage) -> Response;
+
+/// Creates a `404 Not Found` response and logs the invalid target
+fn not_found(request: &Request) -> Response {
+    // Log invalid target and return 404
+    let target_str = str::from_utf8(&request.target).unwrap_or("<non UTF-8>");
+    eprintln!("Invalid request target: {target_str}");
+
+    // Create a 404 response
+    let mut response: Response = ResponseExt::new_404_notfound();
+    response.set_content_length(0);
+    response
+}
 
-fn route(request: Request, config: &Arc<Config>) -> Response {
+fn route(request: Request, config: &Arc<Config>, usage: &mut Usage) -> Response {
     // Routing
+    let metrics_on_main = config.metrics.as_ref().is_some_and(|metrics| metrics.address.is_none());
     match (request.method.as_ref(), request.target.as_ref()) {
         (b"POST", endpoint) if endpoint.starts_with(b"/api/") => {
             // Propagate the response to the minecraft endpoint
-            minecraft::webhook(&request, config)
+            minecraft::webhook(&request, config, usage)
         }
         (b"GET", b"/") => {
             // Serve the web-UI site
             webui::site(&request)
         }
-        _ => {
-            // Log invalid target and return 404
-            let target_str = str::from_utf8(&request.target).unwrap_or("<non UTF-8>");
-            eprintln!("Invalid request target: {target_str}");
-
-            // Create a 404 response
-            let mut response: Response = ResponseExt::new_404_notfound();
-            response.set_content_length(0);
-            response
+        (b"GET", b"/dashboard") => {
+            // Serve the admin dashboard
+            webui::dashboard::dashboard(&request, config, usage)
+        }
+        (b"GET", b"/ready") => {
+            // Serve the readiness status
+            health::ready(config, usage)
         }
+        (b"GET", b"/metrics") if metrics_on_main => {
+            // Serve the metrics
+            metrics::metrics(config)
+        }
+        _ => not_found(&request),
     }
 }
 
+fn route_metrics(request: Request, config: &Arc<Config>, _usage: &mut Usage) -> Response {
+    // Routing for the dedicated metrics listener
+    match (request.method.as_ref(), request.target.as_ref()) {
+        (b"GET", b"/metrics") => metrics::metrics(config),
+        _ => not_found(&request),
+    }
+}
+
+/// Finalizes a response by setting the `Retry-After` header field for temporary failures if configured
+///
+/// # Note
+/// `502` and `504` are not retryable since the command may already have been executed by the RCON server
+fn finalize(response: &mut Response, config: &Config) {
+    let retryable = matches!(response.status.as_ref(), b"429" | b"503");
+    let has_retry_after = response.fields.iter().any(|(key, _)| key.eq_ignore_ascii_case(b"Retry-After"));
+    if let (true, false, Some(retry_after)) = (retryable, has_retry_after, config.server.retry_after) {
+        response.set_field("Retry-After", retry_after.to_string());
+    }
+}
+
+/// Starts a server on the given address and routes all requests via the given router
+fn listen(address: &str, access_log: AccessLog, config: &Arc<Config>, router: Router) -> Result<Infallible, Error> {
+    // Initialize the server
+    let (config_, access_log) = (config.clone(), Arc::new(access_log));
+    let server: Server<_> = Server::new(config.server.connection_limit, move |source, sink| {
+        let (config, access_log) = (config_.clone(), access_log.clone());
+        let peer = accesslog::peer(sink);
+        ehttpd::reqresp(source, sink, move |request| {
+            // Route the request and log the access
+            let start = Instant::now();
+            let entry = Entry::new(&request, peer, &config.server, access_log.format());
+            let mut usage = Usage::default();
+            let mut response = router(request, &config, &mut usage);
+            finalize(&mut response, &config);
+            let budget = entry.budget(&response, &usage, start.elapsed());
+            entry.log(&access_log, &response, &budget);
+            if let (Some(hook), Some(_)) = (usage.hook, &config.metrics) {
+                metrics::record(hook, &budget);
+            }
+            response
+        })
+    });
+
+    // Start the server
+    let Err(e) = server.accept(address);
+
+    // Provide a clear message if the address is already in use
+    let source = e.source.as_ref().and_then(|source| source.downcast_ref::<io::Error>());
+    if source.is_some_and(|source| source.kind() == io::ErrorKind::AddrInUse) {
+        let error = error!("Address {address} is already in use - is another instance running?");
+        return Err(error.with_kind(ErrorKind::AddrInUse));
+    }
+    Err(e.into())
+}
+
+/// Pretty prints a fatal error and exits with the status code for the error category
+fn exit_fatal(e: Error) -> ! {
+    // Print error and backtrace
+    eprintln!("Fatal error: {e}");
+    if e.has_backtrace() && e.kind != ErrorKind::AddrInUse {
+        eprintln!("{}", e.backtrace);
+    }
+
+    // Exit with the status code for the error category
+    process::exit(e.kind.exit_code());
+}
+
 pub fn main() {
     /// The fallible main function code
     fn fallible() -> Result<(), Error> {
-        // Setup periodical database refresh and load config
-        let config = Config::load()?;
+        // Parse the arguments, load config and apply the overrides
+        let args = Args::parse()?;
+        let mut config = Config::load(args.config.as_deref())?;
+        args.apply(&mut config);
 
-        // Initialize the server
-        let config_ = Arc::new(config.clone());
-        let server: Server<_> = Server::new(config.server.connection_limit, move |source, sink| {
-            let config = config_.clone();
-            ehttpd::reqresp(source, sink, move |request| route(request, &config))
-        });
+        // Validate the webhooks only if requested
+        if args.validate_hooks {
+            let problems = minecraft::validate_hooks(&config);
+            for (name, problem) in &problems {
+                eprintln!("Invalid webhook `{name}`: {problem}");
+            }
+
+            // Fail if there are any problems
+            let true = problems.is_empty() else {
+                let error = error!("Found {} problem(s) in the configured webhooks", problems.len());
+                return Err(error.with_kind(ErrorKind::Config));
+            };
+            config.validate()?;
+            eprintln!("All {} webhook(s) are valid", config.webhooks.hooks.len());
+            return Ok(());
+        }
+
+        // Validate the config
+        config.validate()?;
+
+        // Load the state store and start the scheduler
+        state::init(&config)?;
+        let config = Arc::new(config);
+        schedule::spawn(&config)?;
+
+        // Start the dedicated metrics listener if configured
+        if let Some(metrics) = &config.metrics {
+            if let Some(address) = metrics.address.clone() {
+                let format = metrics.access_log.unwrap_or(config.log.access);
+                let file = metrics.access_log_file.as_deref().or(config.log.access_file.as_deref());
+                let access_log = AccessLog::open(format, config.log.budget, file)?;
+                let config = config.clone();
+                thread::spawn(move || {
+                    let Err(e) = listen(&address, access_log, &config, route_metrics);
+                    exit_fatal(e)
+                });
+            }
+        }
 
         // Start the server
-        server.accept(&config.server.address)?;
-        unreachable!("`server.accept` can never exit gracefully")
+        let format = config.server.access_log.unwrap_or(config.log.access);
+        let file = config.server.access_log_file.as_deref().or(config.log.access_file.as_deref());
+        let access_log = AccessLog::open(format, config.log.budget, file)?;
+        let Err(e) = listen(&config.server.address, access_log, &config, route);
+        Err(e)
     }
 
     // Execute the fallible code and pretty print any error
     if let Err(e) = fallible() {
-        // Print error and backtrace
-        eprintln!("Fatal error: {e}");
-        if e.has_backtrace() {
-            eprintln!("{}", e.backtrace);
-        }
-
-        // Exit with abnormal status code
-        process::exit(1);
+        exit_fatal(e);
     }
 }
@@ -0,0 +1,107 @@
+//! A Prometheus metrics endpoint
+
+use crate::{accesslog::Budget, config::Config, minecraft};
+use ehttpd::http::{Response, ResponseExt};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write as _},
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+/// The accumulated request budgets per metrics label
+fn budgets() -> &'static Mutex<BTreeMap<&'static str, Budget>> {
+    /// The accumulated request budgets per metrics label
+    static BUDGETS: OnceLock<Mutex<BTreeMap<&'static str, Budget>>> = OnceLock::new();
+    BUDGETS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// Records the budget of a webhook request for the given metrics label
+pub fn record(label: &'static str, budget: &Budget) {
+    let mut budgets = budgets().lock().unwrap_or_else(PoisonError::into_inner);
+    budgets.entry(label).or_default().add(budget);
+}
+
+/// Escapes a string for use as Prometheus label value
+fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for char_ in string.chars() {
+        match char_ {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            char_ => escaped.push(char_),
+        }
+    }
+    escaped
+}
+
+/// Renders a per-hook counter series with the given name and help text
+fn render_series<'a, T>(metrics: &mut String, name: &str, help: &str, values: impl Iterator<Item = (&'a str, T)>)
+where
+    T: Display,
+{
+    let _ = writeln!(metrics, "# HELP {name} {help}");
+    let _ = writeln!(metrics, "# TYPE {name} counter");
+    for (label, value) in values {
+        let _ = writeln!(metrics, "{name}{{hook=\"{}\"}} {value}", escape(label));
+    }
+}
+
+/// Renders the webhook counters in the Prometheus text exposition format
+fn render(config: &Config) -> String {
+    let counters = minecraft::counters(config);
+    let mut metrics = String::new();
+
+    // Render the invocation counters
+    metrics.push_str("# HELP minecraft_webhook_invocations_total The amount of webhook invocations.\n");
+    metrics.push_str("# TYPE minecraft_webhook_invocations_total counter\n");
+    for (label, (invocations, _)) in &counters {
+        let _ = writeln!(metrics, "minecraft_webhook_invocations_total{{hook=\"{}\"}} {invocations}", escape(label));
+    }
+
+    // Render the error counters
+    metrics.push_str("# HELP minecraft_webhook_errors_total The amount of failed webhook invocations.\n");
+    metrics.push_str("# TYPE minecraft_webhook_errors_total counter\n");
+    for (label, (_, errors)) in &counters {
+        let _ = writeln!(metrics, "minecraft_webhook_errors_total{{hook=\"{}\"}} {errors}", escape(label));
+    }
+
+    // Render the request budgets for the same labels as the counters
+    let budgets = budgets().lock().unwrap_or_else(PoisonError::into_inner).clone();
+    let budgets: Vec<_> =
+        counters.keys().map(|label| (*label, budgets.get(label).copied().unwrap_or_default())).collect();
+    render_series(
+        &mut metrics,
+        "minecraft_webhook_request_bytes_total",
+        "The announced request body sizes of webhook requests.",
+        budgets.iter().map(|(label, budget)| (*label, budget.request_bytes)),
+    );
+    render_series(
+        &mut metrics,
+        "minecraft_webhook_response_bytes_total",
+        "The response body sizes of webhook requests.",
+        budgets.iter().map(|(label, budget)| (*label, budget.response_bytes)),
+    );
+    render_series(
+        &mut metrics,
+        "minecraft_webhook_rcon_seconds_total",
+        "The time spent on RCON round trips for webhook requests.",
+        budgets.iter().map(|(label, budget)| (*label, budget.rcon.as_secs_f64())),
+    );
+    render_series(
+        &mut metrics,
+        "minecraft_webhook_handler_seconds_total",
+        "The total handler time of webhook requests.",
+        budgets.iter().map(|(label, budget)| (*label, budget.total.as_secs_f64())),
+    );
+    metrics
+}
+
+/// Serves the metrics
+pub fn metrics(config: &Config) -> Response {
+    // Create 200 OK response
+    let mut response: Response = ResponseExt::new_200_ok();
+    response.set_field("Content-Type", "text/plain; version=0.0.4; charset=utf-8");
+    response.set_body_data(render(config));
+    response
+}
@@ -2,40 +2,399 @@
 
 mod rcon;
 
-use crate::config::Config;
-use ehttpd::http::{Request, Response, ResponseExt};
+use crate::{
+    accesslog::Usage,
+    config::{Config, WebhookConfig},
+    error::{Error, ErrorKind},
+    minecraft::rcon::RconConnection,
+    pattern::Pattern,
+    state,
+};
+use ehttpd::http::{Request, RequestExt, Response, ResponseExt};
 use sha2::{Digest, Sha512_256};
-use std::{collections::BTreeMap, str, sync::OnceLock};
+use std::{
+    collections::BTreeMap,
+    io, str,
+    sync::{
+        atomic::{AtomicU64, Ordering::SeqCst},
+        OnceLock,
+    },
+    time::Instant,
+};
 
-/// Resolves a webhook command from it's name
-fn lookup_any(name: &[u8], config: &Config) -> Option<&'static String> {
-    /// The hash secret to perform a blinded lookup
+/// A webhook with its lifetime counters
+#[derive(Debug)]
+struct Hook {
+    /// The webhook config
+    config: WebhookConfig,
+    /// The amount of invocations
+    invocations: AtomicU64,
+    /// The amount of failed invocations
+    errors: AtomicU64,
+}
+impl Hook {
+    /// Creates a new hook for the given config
+    const fn new(config: WebhookConfig) -> Self {
+        Self { config, invocations: AtomicU64::new(0), errors: AtomicU64::new(0) }
+    }
+
+    /// The metrics label of the hook
+    fn label(&'static self) -> &'static str {
+        self.config.metrics_label.as_deref().unwrap_or("unlabeled")
+    }
+
+    /// Sets the `X-Hook-Count` and `X-Hook-Errors` header fields if enabled
+    fn set_counters(&self, response: &mut Response, config: &Config) {
+        if config.server.hook_counters {
+            response.set_field("X-Hook-Count", self.invocations.load(SeqCst).to_string());
+            response.set_field("X-Hook-Errors", self.errors.load(SeqCst).to_string());
+        }
+    }
+}
+
+/// The outcome of a webhook execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    /// The command has been executed with the given response
+    Executed(String),
+    /// The guard did not match, so the command has not been executed
+    GuardMismatch,
+}
+
+/// The hash secret to perform a blinded lookup
+fn secret() -> &'static [u8; 32] {
+    /// The hash secret
     static SECRET: OnceLock<[u8; 32]> = OnceLock::new();
-    let secret = SECRET.get_or_init(|| {
+    SECRET.get_or_init(|| {
         // Generate a random secret
         osrandom::to_array().expect("failed to create blinding secret")
-    });
+    })
+}
 
+/// The blinded webhook table
+fn hooks(config: &Config) -> &'static BTreeMap<[u8; 32], Hook> {
     /// The blinded webhook table
-    static HOOKS: OnceLock<BTreeMap<[u8; 32], String>> = OnceLock::new();
-    let hooks = HOOKS.get_or_init(|| {
+    static HOOKS: OnceLock<BTreeMap<[u8; 32], Hook>> = OnceLock::new();
+    HOOKS.get_or_init(|| {
         // Create the blinded hook database
         let mut hooks = BTreeMap::new();
-        for (name, command) in &config.webhooks.hooks {
+        for (name, webhook) in &config.webhooks.hooks {
             // Hash the dict key with the secret
-            let name = Sha512_256::new().chain_update(name).chain_update(secret).finalize();
-            hooks.insert(name.into(), command.clone());
+            let blinded = Sha512_256::new().chain_update(name).chain_update(secret()).finalize();
+            hooks.insert(blinded.into(), Hook::new(webhook.clone()));
         }
         hooks
-    });
+    })
+}
 
+/// Resolves a webhook from it's name
+fn lookup_any(name: &[u8], config: &Config) -> Option<&'static Hook> {
     // Hash the webhook name and look it up
-    let name: [u8; 32] = Sha512_256::new().chain_update(name).chain_update(secret).finalize().into();
-    hooks.get(&name)
+    let name: [u8; 32] = Sha512_256::new().chain_update(name).chain_update(secret()).finalize().into();
+    hooks(config).get(&name)
+}
+
+/// Gets the invocation and error counts of all webhooks, summed up per metrics label
+///
+/// # Note
+/// Hooks without a metrics label are summed up as `unlabeled`; the hook names are never exposed since they are the
+/// webhook secrets.
+pub fn counters(config: &Config) -> BTreeMap<&'static str, (u64, u64)> {
+    let mut counters = BTreeMap::new();
+    for hook in hooks(config).values() {
+        let (invocations, errors) = counters.entry(hook.label()).or_insert((0u64, 0u64));
+        *invocations = invocations.saturating_add(hook.invocations.load(SeqCst));
+        *errors = errors.saturating_add(hook.errors.load(SeqCst));
+    }
+    counters
+}
+
+/// Executes a webhook, including the guard command if any, within a single RCON session
+fn execute(webhook: &WebhookConfig, config: &Config) -> Result<Outcome, Error> {
+    let mut connection = RconConnection::new(config)?;
+    if let (Some(guard_command), Some(guard_glob)) = (&webhook.guard_command, &webhook.guard_glob) {
+        // Execute the guard and check the output
+        let guard_response = connection.send(&state::render(guard_command)?)?;
+        if !guard_glob.matches(&guard_response) {
+            return Ok(Outcome::GuardMismatch);
+        }
+    }
+
+    // Prepare the state update, so that the command is not executed if the state cannot be updated
+    let state_update = match webhook.set_state.is_empty() {
+        true => None,
+        false => Some(state::prepare(&webhook.set_state)?),
+    };
+
+    // Execute the command and update the state
+    let rcon_response = connection.send(&state::render(&webhook.command)?)?;
+    if let Some(values) = state_update {
+        // Don't fail here since the command has already been executed
+        if let Err(e) = state::update(values) {
+            eprintln!("Failed to update the state after executing the command: {e}");
+        }
+    }
+    Ok(Outcome::Executed(rcon_response))
+}
+
+/// Extracts the amount of affected entities from the command output
+fn result_count(webhook: &WebhookConfig, rcon_response: &str) -> Option<u64> {
+    /// The patterns for the common vanilla phrasings
+    static DEFAULTS: OnceLock<Vec<Pattern>> = OnceLock::new();
+    let defaults = DEFAULTS.get_or_init(|| {
+        // Compile the default patterns
+        let patterns = [
+            "Killed # entities*",
+            "Teleported # entities*",
+            "* to # entities*",
+            "* to # targets*",
+            "* to # players*",
+            "* from # entities*",
+            "* from # targets*",
+            "* from # players*",
+            "Test passed, count: #*",
+            "There are # of a max of*",
+        ];
+        patterns.iter().map(|pattern| Pattern::new(pattern).expect("invalid default result count pattern")).collect()
+    });
+
+    // Use the first matching pattern
+    let patterns = match webhook.result_count_glob.is_empty() {
+        true => defaults,
+        false => &webhook.result_count_glob,
+    };
+    patterns.iter().find_map(|pattern| pattern.capture_number(rcon_response))
+}
+
+/// Whether the request prefers a JSON response via the `Accept` header field
+fn accepts_json(request: &Request) -> bool {
+    let Some(accept) = request.field("Accept") else {
+        return false;
+    };
+    accept.split(|byte| *byte == b',').any(|media_type| {
+        // Strip parameters like `;q=0.9` and compare the media type
+        let media_type = media_type.split(|byte| *byte == b';').next().unwrap_or_default();
+        media_type.trim_ascii().eq_ignore_ascii_case(b"application/json")
+    })
+}
+
+/// Renders the command output and the result count as JSON object
+fn json_body(rcon_response: &str, count: Option<u64>) -> String {
+    let mut json = String::from("{\"output\": ");
+    state::json_string(rcon_response, &mut json);
+    match count {
+        Some(count) => json.push_str(&format!(", \"count\": {count}}}\n")),
+        None => json.push_str(", \"count\": null}\n"),
+    }
+    json
+}
+
+/// Collapses each run of blank lines into its first blank line and leaves all other lines untouched
+fn collapse_blank_lines(rcon_response: &str) -> String {
+    let mut collapsed = String::with_capacity(rcon_response.len());
+    let mut previous_blank = false;
+    for line in rcon_response.split_inclusive('\n') {
+        // Skip the line if it continues a run of blank lines
+        let blank = line.trim().is_empty();
+        if !(blank && previous_blank) {
+            collapsed.push_str(line);
+        }
+        previous_blank = blank;
+    }
+    collapsed
+}
+
+/// Collapses blank lines and trims the RCON response if configured
+///
+/// # Note
+/// Both options are independent: collapsing keeps leading and trailing whitespace (except for redundant blank lines),
+/// and trimming keeps the internal blank lines.
+fn trim_response(webhook: &WebhookConfig, config: &Config, rcon_response: String) -> String {
+    let trim = webhook.trim_response.unwrap_or(config.server.trim_response);
+    let collapse = webhook.trim_collapse_newlines.unwrap_or(config.server.trim_collapse_newlines);
+    let rcon_response = match collapse {
+        true => collapse_blank_lines(&rcon_response),
+        false => rcon_response,
+    };
+    match trim {
+        true => rcon_response.trim().to_string(),
+        false => rcon_response,
+    }
+}
+
+/// Returns the HTTP status code for a failed webhook execution
+///
+/// # Note
+/// If the connection to the RCON server cannot be established, nothing has been sent yet and the status is `503`, which
+/// is safe to retry. Once the connection is established, a command may already have been executed: If the RCON server
+/// stops responding, the status is `504`; if the connection fails otherwise, the status is `502`. All other errors
+/// (e.g. a full state store) are `500`.
+fn error_status(error: &Error) -> u16 {
+    // Only I/O errors are upstream failures
+    match error.kind {
+        ErrorKind::Unavailable => return 503,
+        ErrorKind::Io => (),
+        _ => return 500,
+    }
+
+    // Classify the I/O error
+    let source = error.source.as_ref().and_then(|source| source.downcast_ref::<io::Error>());
+    match source.map(io::Error::kind) {
+        Some(io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => 504,
+        _ => 502,
+    }
+}
+
+/// Returns the reason phrase for the given HTTP status code
+const fn status_reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        203 => "Non-Authoritative Information",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        208 => "Already Reported",
+        226 => "IM Used",
+        300 => "Multiple Choices",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        305 => "Use Proxy",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        402 => "Payment Required",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        418 => "I'm a teapot",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Content",
+        423 => "Locked",
+        424 => "Failed Dependency",
+        425 => "Too Early",
+        426 => "Upgrade Required",
+        428 => "Precondition Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        451 => "Unavailable For Legal Reasons",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        506 => "Variant Also Negotiates",
+        507 => "Insufficient Storage",
+        508 => "Loop Detected",
+        510 => "Not Extended",
+        511 => "Network Authentication Required",
+        // Use a generic phrase for the status class
+        _ => match status / 100 {
+            2 => "Success",
+            3 => "Redirection",
+            4 => "Client Error",
+            _ => "Server Error",
+        },
+    }
+}
+
+/// Validates a command and pushes the problems if any
+fn validate_command<'a>(name: &'a str, field: &str, command: &str, problems: &mut Vec<(&'a str, String)>) {
+    if command.trim().is_empty() {
+        problems.push((name, format!("{field} is empty")));
+    }
+    if command.contains(['\r', '\n', '\0']) {
+        problems.push((name, format!("{field} contains a line break or NUL byte")));
+    }
+    if command.len() > RconConnection::COMMAND_SIZE_MAX {
+        let limit = RconConnection::COMMAND_SIZE_MAX;
+        problems.push((name, format!("{field} is too long ({} bytes, limit {limit})", command.len())));
+    }
+}
+
+/// Validates the configured webhooks without connecting to the RCON server and returns the problems for each hook
+pub fn validate_hooks(config: &Config) -> Vec<(&str, String)> {
+    let mut problems = config.webhook_problems();
+    for (name, webhook) in &config.webhooks.hooks {
+        // Validate the hook name; it is matched against the undecoded request target
+        let is_valid_char = |byte: u8| byte.is_ascii_graphic() && !matches!(byte, b'?' | b'#' | b'%');
+        if name.is_empty() || !name.bytes().all(is_valid_char) {
+            let problem =
+                "name must be non-empty and must only contain visible ASCII characters except `?`, `#` and `%`";
+            problems.push((name.as_str(), problem.to_string()));
+        }
+
+        // Validate the commands
+        validate_command(name, "command", &webhook.command, &mut problems);
+        if let Some(guard_command) = &webhook.guard_command {
+            validate_command(name, "guard_command", guard_command, &mut problems);
+        }
+
+        // Validate the result count patterns
+        if webhook.result_count_glob.iter().any(|pattern| !pattern.has_capture()) {
+            let problem = "result_count_glob contains a pattern without `#`, which never yields a count";
+            problems.push((name.as_str(), problem.to_string()));
+        }
+    }
+
+    // Validate the schedules
+    for schedule in &config.schedules {
+        if !config.webhooks.hooks.contains_key(&schedule.hook) {
+            problems.push((schedule.hook.as_str(), "webhook is scheduled but not defined".to_string()));
+        }
+    }
+
+    // Group the problems by hook
+    problems.sort_by_key(|(name, _)| *name);
+    problems
+}
+
+/// Executes a single RCON command within a new RCON session
+pub fn exec(config: &Config, command: &str) -> Result<String, Error> {
+    let mut connection = RconConnection::new(config)?;
+    connection.send(command)
+}
+
+/// Executes a webhook by name outside of an HTTP request (e.g. from the scheduler) and logs the result
+pub fn trigger(name: &str, config: &Config) {
+    let Some(hook) = lookup_any(name.as_bytes(), config) else {
+        eprintln!("Invalid webhook name: {name}");
+        return;
+    };
+
+    // Execute RCON command
+    hook.invocations.fetch_add(1, SeqCst);
+    match execute(&hook.config, config) {
+        Ok(Outcome::Executed(rcon_response)) => eprintln!("Executed webhook `{name}`: {rcon_response}"),
+        Ok(Outcome::GuardMismatch) => eprintln!("Skipped webhook `{name}`: guard did not match"),
+        Err(e) => {
+            // Log error
+            hook.errors.fetch_add(1, SeqCst);
+            eprintln!("Failed to execute RCON command: {e}");
+            if e.has_backtrace() {
+                eprintln!("{}", e.backtrace);
+            }
+        }
+    }
 }
 
 /// Performs a webhook
-pub fn webhook(request: &Request, config: &Config) -> Response {
+pub fn webhook(request: &Request, config: &Config, usage: &mut Usage) -> Response {
     // Deny non-post requests
     if request.method != b"POST" {
         // Log invalid method and return 405
@@ -48,9 +407,9 @@ pub fn webhook(request: &Request, config: &Config) -> Response {
         return response;
     }
 
-    // Lookup webhook command
+    // Lookup webhook
     let name = request.target.strip_prefix(b"/api/").expect("called endpoint with invalid prefix");
-    let Some(command) = lookup_any(name, config) else {
+    let Some(hook) = lookup_any(name, config) else {
         // Log invalid target and return 404
         let target_str = str::from_utf8(&request.target).unwrap_or("<non UTF-8>");
         eprintln!("Invalid webhook name: {target_str}");
@@ -62,25 +421,121 @@ pub fn webhook(request: &Request, config: &Config) -> Response {
     };
 
     // Execute RCON command
-    match rcon::exec(config, command) {
-        Ok(rcon_response) => {
+    hook.invocations.fetch_add(1, SeqCst);
+    usage.hook = Some(hook.label());
+    let start = Instant::now();
+    let result = execute(&hook.config, config);
+    usage.rcon = usage.rcon.saturating_add(start.elapsed());
+    match result {
+        Ok(Outcome::Executed(rcon_response)) => {
             // Create 200 OK response
+            let rcon_response = trim_response(&hook.config, config, rcon_response);
+            let count = result_count(&hook.config, &rcon_response);
             let mut response: Response = ResponseExt::new_200_ok();
+            if let Some(count) = count {
+                response.set_field("X-Result-Count", count.to_string());
+            }
+
+            // Set the body as JSON if requested or as plain text otherwise
+            if accepts_json(request) {
+                response.set_field("Content-Type", "application/json");
+                response.set_body_data(json_body(&rcon_response, count));
+            } else {
+                response.set_field("Content-Type", "text/plain");
+                response.set_body_data(rcon_response);
+            }
+            hook.set_counters(&mut response, config);
+            response
+        }
+        Ok(Outcome::GuardMismatch) => {
+            // Create the configured guard response
+            let status = hook.config.guard_status;
+            let mut response: Response = ResponseExt::new_status_reason(status, status_reason(status));
             response.set_field("Content-Type", "text/plain");
-            response.set_body_data(rcon_response);
+            response.set_body_data(hook.config.guard_message.clone());
+            hook.set_counters(&mut response, config);
             response
         }
         Err(e) => {
             // Log error
+            hook.errors.fetch_add(1, SeqCst);
             eprintln!("Failed to execute RCON command: {e}");
             if e.has_backtrace() {
                 eprintln!("{}", e.backtrace);
             }
 
-            // Create 500 response
-            let mut response: Response = ResponseExt::new_500_internalservererror();
+            // Create the error response
+            let status = error_status(&e);
+            let mut response: Response = ResponseExt::new_status_reason(status, status_reason(status));
             response.set_content_length(0);
+            hook.set_counters(&mut response, config);
             response
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{error_status, trim_response};
+    use crate::{
+        config::Config,
+        error,
+        error::{Error, ErrorKind},
+    };
+    use std::io;
+
+    /// Trims the response with the given server defaults and the options of the webhook `hook`
+    fn trim(server: &str, hook: &str, rcon_response: &str) -> String {
+        let config = format!(
+            "[server]\naddress = \"127.0.0.1:8080\"\n{server}\n[rcon]\naddress = \"127.0.0.1:25575\"\n\
+             [webhooks.hook]\ncommand = \"list\"\n{hook}"
+        );
+        let config: Config = toml::from_str(&config).expect("invalid test config");
+        let webhook = config.webhooks.hooks.get("hook").expect("missing test webhook");
+        trim_response(webhook, &config, rcon_response.to_string())
+    }
+
+    #[test]
+    fn trim_response_is_off_by_default() {
+        assert_eq!(trim("", "", "  a\n\n\nb \n"), "  a\n\n\nb \n");
+    }
+
+    #[test]
+    fn trim_response_trims_only() {
+        assert_eq!(trim("trim_response = true", "", "  a\n\n\nb \n"), "a\n\n\nb");
+    }
+
+    #[test]
+    fn trim_response_collapses_only() {
+        let collapse = "trim_collapse_newlines = true";
+        assert_eq!(trim(collapse, "", "  a\n\n \n\nb\n\nc \n"), "  a\n\nb\n\nc \n");
+        assert_eq!(trim(collapse, "", "\r\n\r\na\r\n\r\n\r\nb\r\n"), "\r\na\r\n\r\nb\r\n");
+        assert_eq!(trim(collapse, "", "a\n\n\n"), "a\n\n");
+    }
+
+    #[test]
+    fn trim_response_collapses_and_trims() {
+        let server = "trim_response = true\ntrim_collapse_newlines = true";
+        assert_eq!(trim(server, "", "\n\n  a\n\n\n\nb \n\n"), "a\n\nb");
+    }
+
+    #[test]
+    fn trim_response_honors_webhook_overrides() {
+        let server = "trim_response = true\ntrim_collapse_newlines = true";
+        let hook = "trim_response = false\ntrim_collapse_newlines = false";
+        assert_eq!(trim(server, hook, " a\n\n\nb "), " a\n\n\nb ");
+        assert_eq!(trim("", "trim_collapse_newlines = true", " a\n\n\nb "), " a\n\nb ");
+    }
+
+    #[test]
+    fn error_status_classifies_upstream_failures() {
+        let io_error = |kind: io::ErrorKind| Error::from(io::Error::from(kind));
+        assert_eq!(error_status(&io_error(io::ErrorKind::ConnectionRefused).with_kind(ErrorKind::Unavailable)), 503);
+        assert_eq!(error_status(&io_error(io::ErrorKind::TimedOut).with_kind(ErrorKind::Unavailable)), 503);
+        assert_eq!(error_status(&io_error(io::ErrorKind::TimedOut)), 504);
+        assert_eq!(error_status(&io_error(io::ErrorKind::WouldBlock)), 504);
+        assert_eq!(error_status(&io_error(io::ErrorKind::ConnectionReset)), 502);
+        assert_eq!(error_status(&io_error(io::ErrorKind::UnexpectedEof)), 502);
+        assert_eq!(error_status(&error!("State store is full")), 500);
+    }
+}
@@ -1,8 +1,12 @@
 //! An implementation of the RCON API
 
-use crate::{config::Config, error, error::Error};
+use crate::{
+    config::Config,
+    error,
+    error::{Error, ErrorKind},
+};
 use std::{
-    io::{Read, Write},
+    io::{self, Read, Write},
     net::{TcpStream, ToSocketAddrs},
     str,
     sync::atomic::{AtomicI32, Ordering::SeqCst},
@@ -17,29 +21,37 @@ static ID_COUNTER: AtomicI32 = AtomicI32::new(0);
 pub struct RconConnection {
     /// The underlying connection
     connection: TcpStream,
+    /// The maximum size of a response packet
+    size_max: i32,
 }
 impl RconConnection {
     /// The metadata size within an RCON message (**excluding** the length field)
     const META_SIZE: usize = 4 + 4 + 2;
     /// The timeout of RCON connections
     const TIMEOUT: Duration = Duration::from_secs(10);
-    /// The maximum size of an RCON message
-    const SIZE_MAX: i32 = 4110; // https://wiki.vg/Rcon#Fragmentation
+    /// The maximum size of an RCON command payload
+    pub const COMMAND_SIZE_MAX: usize = 1446; // https://wiki.vg/Rcon#Fragmentation
 
     /// Creates a new RCON connection
     pub fn new(config: &Config) -> Result<Self, Error> {
+        /// Marks a connect-phase error as unavailable since nothing has been sent yet
+        fn unavailable(error: io::Error) -> Error {
+            Error::from(error).with_kind(ErrorKind::Unavailable)
+        }
+
         // Parse the remote address
-        let Some(address) = config.rcon.address.to_socket_addrs()?.next() else {
+        let Some(address) = config.rcon.address.to_socket_addrs().map_err(unavailable)?.next() else {
             return Err(error!("Failed to parse RCON address"));
         };
 
         // Connect and configure socket
-        let connection = TcpStream::connect_timeout(&address, Self::TIMEOUT)?;
+        let connection = TcpStream::connect_timeout(&address, Self::TIMEOUT).map_err(unavailable)?;
         connection.set_read_timeout(Some(Self::TIMEOUT))?;
         connection.set_write_timeout(Some(Self::TIMEOUT))?;
 
         // Init self and authenticate if necessary
-        let mut this = Self { connection };
+        let size_max = i32::try_from(config.rcon.packet_size_max)?;
+        let mut this = Self { connection, size_max };
         if let Some(password) = &config.rcon.password {
             // Perform an authentication transaction
             this.transaction(3, password)?;
@@ -62,18 +74,19 @@ impl RconConnection {
         // Read size field
         let mut size_bytes = [0; 4];
         self.connection.read_exact(&mut size_bytes)?;
-        let size @ 0..=Self::SIZE_MAX = i32::from_le_bytes(size_bytes) else {
+        let size = i32::from_le_bytes(size_bytes);
+        let true = (0..=self.size_max).contains(&size) else {
             // Return error
-            return Err(error!("Announced RCON response is too large ({})", i32::from_le_bytes(size_bytes)));
+            return Err(error!("Announced RCON response is too large ({size})"));
         };
 
         // Prepare message buffer
-        #[allow(clippy::arithmetic_side_effects, reason = "SIZE_MAX is significantly smaller than usize::MAX")]
+        #[allow(clippy::arithmetic_side_effects, reason = "Size is constrained by i32::MAX")]
         let mut response = Vec::with_capacity(4 + size as usize);
         response.extend(size_bytes);
 
         // Expand the buffer with 4 trailing `0` bytes
-        #[allow(clippy::arithmetic_side_effects, reason = "SIZE_MAX is significantly smaller than usize::MAX")]
+        #[allow(clippy::arithmetic_side_effects, reason = "Size is constrained by i32::MAX")]
         response.resize(4 + size as usize, 0);
 
         // Read and parse response
@@ -142,9 +155,3 @@ impl RconConnection {
         Ok((id, type_, body))
     }
 }
-
-/// Executes an RCON command (oneshot for `RconConnection::new` + `RconConnection::send`)
-pub fn exec(config: &Config, command: &str) -> Result<String, Error> {
-    let mut connection = RconConnection::new(config)?;
-    connection.send(command)
-}
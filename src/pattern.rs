@@ -0,0 +1,271 @@
+//! A minimal glob-style pattern matcher
+
+use crate::{error, error::Error};
+use serde::Deserialize;
+
+/// A pattern token
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A literal character
+    Literal(char),
+    /// Any single character (`?`)
+    Any,
+    /// Any sequence of characters including the empty sequence (`*`)
+    Star,
+    /// A captured non-negative decimal number (`#`)
+    Number,
+    /// A character class (`[a-z]` or `[!0]`)
+    Class {
+        /// Whether the class is negated
+        negated: bool,
+        /// The inclusive character ranges
+        ranges: Vec<(char, char)>,
+    },
+}
+impl Token {
+    /// Whether the token matches the given character (always `false` for `Star`)
+    fn matches(&self, char_: char) -> bool {
+        match self {
+            Self::Literal(literal) => *literal == char_,
+            Self::Any => true,
+            Self::Star | Self::Number => false,
+            Self::Class { negated, ranges } => {
+                let contained = ranges.iter().any(|(start, end)| (*start..=*end).contains(&char_));
+                contained != *negated
+            }
+        }
+    }
+}
+
+/// A glob-style pattern that is matched against an entire string
+///
+/// # Syntax
+/// - `*` matches any sequence of characters (including none)
+/// - `?` matches any single character
+/// - `[abc]`, `[a-z]` match a single character of the class; `[!...]` negates the class
+/// - `#` matches a decimal number; the first matched number is captured
+/// - `\` escapes the following character
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern {
+    /// The pattern tokens
+    tokens: Vec<Token>,
+}
+impl Pattern {
+    /// Parses a pattern
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(char_) = chars.next() {
+            let token = match char_ {
+                '*' => Token::Star,
+                '?' => Token::Any,
+                '#' => Token::Number,
+                '[' => Self::parse_class(&mut chars, pattern)?,
+                '\\' => match chars.next() {
+                    Some(escaped) => Token::Literal(escaped),
+                    None => return Err(error!("Truncated escape sequence in pattern: {pattern}")),
+                },
+                literal => Token::Literal(literal),
+            };
+            tokens.push(token);
+        }
+        Ok(Self { tokens })
+    }
+
+    /// Whether the pattern contains a `#` to capture a number
+    pub fn has_capture(&self) -> bool {
+        self.tokens.contains(&Token::Number)
+    }
+
+    /// Checks whether the pattern matches the entire string
+    pub fn matches(&self, string: &str) -> bool {
+        self.match_captured(string).is_some()
+    }
+
+    /// Matches the pattern against the entire string and returns the first captured number if any
+    pub fn capture_number(&self, string: &str) -> Option<u64> {
+        self.match_captured(string)?
+    }
+
+    /// Matches the pattern against the entire string and returns the first captured number if the pattern matches
+    fn match_captured(&self, string: &str) -> Option<Option<u64>> {
+        let chars: Vec<char> = string.chars().collect();
+        let (mut token_pos, mut char_pos) = (0, 0);
+        let mut backtrack: Option<(usize, usize)> = None;
+        let mut capture: Option<(usize, u64)> = None;
+
+        #[allow(clippy::arithmetic_side_effects, reason = "Positions are bounded by the token and char counts")]
+        while char_pos < chars.len() {
+            match (self.tokens.get(token_pos), chars.get(char_pos)) {
+                (Some(Token::Star), _) => {
+                    // Remember the star position and try to match the empty sequence first
+                    backtrack = Some((token_pos, char_pos));
+                    token_pos += 1;
+                }
+                (Some(Token::Number), Some(char_)) if char_.is_ascii_digit() => {
+                    // Consume all digits and capture the number if it is the first one
+                    let digits: String =
+                        chars.iter().skip(char_pos).take_while(|digit| digit.is_ascii_digit()).collect();
+                    if capture.is_none() {
+                        capture = digits.parse().ok().map(|number| (token_pos, number));
+                    }
+                    token_pos += 1;
+                    char_pos += digits.len();
+                }
+                (Some(token), Some(char_)) if token.matches(*char_) => {
+                    // Consume the character
+                    token_pos += 1;
+                    char_pos += 1;
+                }
+                _ => match backtrack {
+                    // Let the last star consume one more character
+                    Some((star_pos, star_char_pos)) => {
+                        // Discard captures that have been made after the star
+                        capture = capture.filter(|(capture_pos, _)| *capture_pos < star_pos);
+                        backtrack = Some((star_pos, star_char_pos + 1));
+                        token_pos = star_pos + 1;
+                        char_pos = star_char_pos + 1;
+                    }
+                    None => return None,
+                },
+            }
+        }
+
+        // Only trailing stars may remain
+        let remaining = self.tokens.get(token_pos..).unwrap_or_default();
+        let true = remaining.iter().all(|token| *token == Token::Star) else {
+            return None;
+        };
+        Some(capture.map(|(_, number)| number))
+    }
+
+    /// Parses a character class after the opening `[`
+    fn parse_class(chars: &mut std::str::Chars, pattern: &str) -> Result<Token, Error> {
+        // Check for negation
+        let mut peek = chars.clone();
+        let negated = peek.next() == Some('!');
+        if negated {
+            *chars = peek;
+        }
+
+        // Parse the ranges
+        let mut ranges = Vec::new();
+        loop {
+            let start = match chars.next() {
+                Some(']') if !ranges.is_empty() => break,
+                Some('\\') => chars.next(),
+                other => other,
+            };
+            let Some(start) = start else {
+                return Err(error!("Unterminated character class in pattern: {pattern}"));
+            };
+
+            // Check for a range
+            let mut peek = chars.clone();
+            match (peek.next(), peek.next()) {
+                (Some('-'), Some(end)) if end != ']' => {
+                    *chars = peek;
+                    ranges.push((start, end));
+                }
+                _ => ranges.push((start, start)),
+            }
+        }
+        Ok(Token::Class { negated, ranges })
+    }
+}
+impl TryFrom<String> for Pattern {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+
+    /// Compiles a pattern
+    fn pattern(pattern: &str) -> Pattern {
+        Pattern::new(pattern).expect("invalid test pattern")
+    }
+
+    #[test]
+    fn literals() {
+        assert!(pattern("list").matches("list"));
+        assert!(!pattern("list").matches("lists"));
+        assert!(!pattern("list").matches("lis"));
+        assert!(pattern("").matches(""));
+    }
+
+    #[test]
+    fn stars() {
+        assert!(pattern("*").matches(""));
+        assert!(pattern("*").matches("anything"));
+        assert!(pattern("There are *").matches("There are 3 players"));
+        assert!(pattern("* online").matches("3 players online"));
+        assert!(pattern("a**b").matches("ab"));
+        assert!(!pattern("a*b").matches("ac"));
+    }
+
+    #[test]
+    fn any() {
+        assert!(pattern("a?c").matches("abc"));
+        assert!(!pattern("a?c").matches("ac"));
+    }
+
+    #[test]
+    fn backtracking() {
+        assert!(pattern("*ab").matches("aab"));
+        assert!(pattern("*a*b").matches("xxaxxaxxb"));
+        assert!(pattern("a*b*c").matches("abbbc"));
+        assert!(!pattern("a*b*c").matches("abbb"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(pattern("[abc]").matches("b"));
+        assert!(!pattern("[abc]").matches("d"));
+        assert!(pattern("[a-z]x").matches("qx"));
+        assert!(!pattern("[a-z]x").matches("Qx"));
+        assert!(pattern("[]]").matches("]"));
+        assert!(pattern("[a-]").matches("-"));
+        assert!(Pattern::new("[abc").is_err());
+    }
+
+    #[test]
+    fn negation() {
+        assert!(pattern("There are [!0]*").matches("There are 3 players"));
+        assert!(!pattern("There are [!0]*").matches("There are 0 players"));
+        assert!(!pattern("[!a-z]").matches("q"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(pattern("\\*").matches("*"));
+        assert!(!pattern("\\*").matches("x"));
+        assert!(pattern("\\#\\?\\[").matches("#?["));
+        assert!(pattern("[\\]]").matches("]"));
+        assert!(Pattern::new("abc\\").is_err());
+    }
+
+    #[test]
+    fn capture() {
+        assert_eq!(pattern("Killed # entities").capture_number("Killed 42 entities"), Some(42));
+        assert_eq!(pattern("# and #").capture_number("1 and 2"), Some(1));
+        assert_eq!(pattern("Killed # entities").capture_number("Killed x entities"), None);
+        assert_eq!(pattern("Killed *").capture_number("Killed 42 entities"), None);
+        assert!(pattern("Killed # entities").has_capture());
+        assert!(!pattern("Killed \\# entities").has_capture());
+        assert!(pattern("#").matches("123"));
+        assert!(!pattern("#").matches(""));
+    }
+
+    #[test]
+    fn capture_discarded_on_backtrack() {
+        // The first attempt captures `1` but fails on `y`, so the capture must be discarded
+        assert_eq!(pattern("* #x").capture_number("a 1y 2x"), Some(2));
+        assert_eq!(pattern("*to # players*").capture_number("Gave 5 to 3 players"), Some(3));
+    }
+}
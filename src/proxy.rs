@@ -0,0 +1,253 @@
+//! Client address resolution behind trusted reverse proxies
+
+use crate::{config::ForwardedHeader, error, error::Error};
+use ehttpd::bytes::Data;
+use serde::Deserialize;
+use std::{net::IpAddr, str};
+
+/// An IP network in CIDR notation (e.g. `10.0.0.0/8` or `::1/128`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    /// The network address
+    address: IpAddr,
+    /// The prefix length
+    prefix: u32,
+}
+impl Cidr {
+    /// Parses a CIDR network; a plain address is treated as a single-host network
+    pub fn new(cidr: &str) -> Result<Self, Error> {
+        // Split address and prefix length
+        let (address, prefix) = match cidr.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (cidr, None),
+        };
+
+        // Parse the address and the prefix length
+        let address = address.parse::<IpAddr>().map_err(|e| error!(with: e, "Invalid CIDR address: {cidr}"))?;
+        let prefix_max = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u32>().map_err(|e| error!(with: e, "Invalid CIDR prefix: {cidr}"))?,
+            None => prefix_max,
+        };
+
+        // Validate the prefix
+        let true = prefix <= prefix_max else {
+            return Err(error!("CIDR prefix is too large: {cidr}"));
+        };
+        Ok(Self { address, prefix })
+    }
+
+    /// Checks whether the given address is within the network
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32u32.saturating_sub(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128u32.saturating_sub(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+impl TryFrom<String> for Cidr {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(&value)
+    }
+}
+
+/// Parses a forwarded hop, which may be enclosed in quotes and may contain a port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    // Strip quotes and brackets/ports
+    let hop = hop.trim().trim_matches('"');
+    let address = match hop.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?.0,
+        None if hop.matches(':').count() == 1 => hop.split_once(':')?.0,
+        None => hop,
+    };
+    address.parse().ok()
+}
+
+/// Collects the forwarded hops from the given forwarded header field
+///
+/// # Note
+/// The hops are returned in header order, i.e. the original client first and the closest proxy last. `None` denotes an
+/// unparsable or obfuscated hop. The other forwarded header field is ignored, since a trusted proxy only maintains the
+/// one it is configured for and passes the other one through from the client unchanged.
+fn forwarded_hops(fields: &[(Data, Data)], header: ForwardedHeader) -> Vec<Option<IpAddr>> {
+    // Collect all values for a given field name in order
+    let values = |name: &str| -> Vec<&str> {
+        let fields = fields.iter().filter(|(key, _)| key.eq_ignore_ascii_case(name.as_bytes()));
+        fields.map(|(_, value)| str::from_utf8(value).unwrap_or_default()).collect()
+    };
+
+    match header {
+        ForwardedHeader::Forwarded => {
+            // Parse the `for` parameter of each element
+            let forwarded = values("Forwarded");
+            let elements = forwarded.iter().flat_map(|value| value.split(','));
+            let hops = elements.map(|element| {
+                let mut pairs = element.split(';').filter_map(|pair| pair.split_once('='));
+                let for_ = pairs.find(|(key, _)| key.trim().eq_ignore_ascii_case("for"));
+                for_.and_then(|(_, value)| parse_hop(value))
+            });
+            hops.collect()
+        }
+        ForwardedHeader::XForwardedFor => {
+            // Parse each comma-separated element
+            let forwarded_for = values("X-Forwarded-For");
+            forwarded_for.iter().flat_map(|value| value.split(',')).map(parse_hop).collect()
+        }
+    }
+}
+
+/// Resolves the real client address
+///
+/// # Note
+/// Forwarded header fields are only honored if the immediate peer is a trusted proxy. In this case, the forwarded chain
+/// is walked from the closest hop backwards, skipping all trusted proxies; the first untrusted hop is the client. If a
+/// hop is unparsable, the last verified hop is used.
+pub fn client_address(peer: IpAddr, fields: &[(Data, Data)], trusted: &[Cidr], header: ForwardedHeader) -> IpAddr {
+    let is_trusted = |address: &IpAddr| trusted.iter().any(|cidr| cidr.contains(address));
+    if !is_trusted(&peer) {
+        // Ignore any forwarded header fields from untrusted peers
+        return peer;
+    }
+
+    // Walk the chain from the closest hop backwards
+    let mut client = peer;
+    for hop in forwarded_hops(fields, header).into_iter().rev() {
+        let Some(hop) = hop else {
+            // Stop at unparsable hops and use the last verified address
+            return client;
+        };
+
+        // Stop at the first untrusted hop
+        client = hop;
+        if !is_trusted(&hop) {
+            return client;
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_address, Cidr};
+    use crate::config::ForwardedHeader;
+    use ehttpd::bytes::Data;
+    use std::net::IpAddr;
+
+    /// Parses an IP address
+    fn ip(address: &str) -> IpAddr {
+        address.parse().expect("invalid test address")
+    }
+
+    /// Parses a CIDR network
+    fn cidr(cidr: &str) -> Cidr {
+        Cidr::new(cidr).expect("invalid test network")
+    }
+
+    /// Creates a header field list
+    fn fields(fields: &[(&'static str, &'static str)]) -> Vec<(Data, Data)> {
+        fields.iter().map(|(key, value)| (Data::from(*key), Data::from(*value))).collect()
+    }
+
+    #[test]
+    fn cidr_contains() {
+        assert!(cidr("0.0.0.0/0").contains(&ip("203.0.113.7")));
+        assert!(!cidr("0.0.0.0/0").contains(&ip("::1")));
+        assert!(cidr("::/0").contains(&ip("2001:db8::1")));
+        assert!(cidr("10.0.0.0/8").contains(&ip("10.1.2.3")));
+        assert!(!cidr("10.0.0.0/8").contains(&ip("11.0.0.0")));
+        assert!(cidr("127.0.0.1/32").contains(&ip("127.0.0.1")));
+        assert!(!cidr("127.0.0.1/32").contains(&ip("127.0.0.2")));
+        assert!(cidr("127.0.0.1").contains(&ip("::ffff:127.0.0.1")));
+        assert!(cidr("2001:db8::1/128").contains(&ip("2001:db8::1")));
+        assert!(!cidr("2001:db8::1/128").contains(&ip("2001:db8::2")));
+    }
+
+    #[test]
+    fn cidr_invalid() {
+        assert!(Cidr::new("10.0.0.0/33").is_err());
+        assert!(Cidr::new("::/129").is_err());
+        assert!(Cidr::new("10.0.0.0/x").is_err());
+        assert!(Cidr::new("example.org").is_err());
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let fields = fields(&[("X-Forwarded-For", "6.6.6.6"), ("Forwarded", "for=6.6.6.6")]);
+        let trusted = [cidr("10.0.0.0/8")];
+        for header in [ForwardedHeader::XForwardedFor, ForwardedHeader::Forwarded] {
+            assert_eq!(client_address(ip("203.0.113.7"), &fields, &trusted, header), ip("203.0.113.7"));
+        }
+    }
+
+    #[test]
+    fn trusted_peer() {
+        let trusted = [cidr("127.0.0.1")];
+        let xff = fields(&[("X-Forwarded-For", "203.0.113.7")]);
+        assert_eq!(client_address(ip("127.0.0.1"), &xff, &trusted, ForwardedHeader::XForwardedFor), ip("203.0.113.7"));
+        let forwarded = fields(&[("Forwarded", "for=203.0.113.7;proto=https")]);
+        assert_eq!(
+            client_address(ip("127.0.0.1"), &forwarded, &trusted, ForwardedHeader::Forwarded),
+            ip("203.0.113.7")
+        );
+
+        // Without a forwarded header field, the peer is the client
+        assert_eq!(client_address(ip("127.0.0.1"), &[], &trusted, ForwardedHeader::XForwardedFor), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn multi_hop() {
+        // Skip all trusted proxies from the closest hop backwards; the first untrusted hop is the client
+        let trusted = [cidr("127.0.0.1"), cidr("10.0.0.0/8")];
+        let xff = fields(&[("X-Forwarded-For", "6.6.6.6, 203.0.113.7"), ("X-Forwarded-For", "10.0.0.2, 10.0.0.1")]);
+        assert_eq!(client_address(ip("127.0.0.1"), &xff, &trusted, ForwardedHeader::XForwardedFor), ip("203.0.113.7"));
+
+        // If all hops are trusted, the first hop is the client
+        let xff = fields(&[("X-Forwarded-For", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(client_address(ip("127.0.0.1"), &xff, &trusted, ForwardedHeader::XForwardedFor), ip("10.0.0.3"));
+
+        // Stop at unparsable hops and use the last verified address
+        let forwarded = fields(&[("Forwarded", "for=203.0.113.7, for=_hidden, for=10.0.0.1")]);
+        assert_eq!(client_address(ip("127.0.0.1"), &forwarded, &trusted, ForwardedHeader::Forwarded), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn spoofed_header_is_ignored() {
+        // A proxy that only appends `X-Forwarded-For` passes a client-supplied `Forwarded` header field through
+        let trusted = [cidr("127.0.0.1")];
+        let headers = fields(&[("Forwarded", "for=6.6.6.6"), ("X-Forwarded-For", "203.0.113.7")]);
+        assert_eq!(
+            client_address(ip("127.0.0.1"), &headers, &trusted, ForwardedHeader::XForwardedFor),
+            ip("203.0.113.7")
+        );
+
+        // And vice versa
+        let headers = fields(&[("X-Forwarded-For", "6.6.6.6"), ("Forwarded", "for=203.0.113.7")]);
+        assert_eq!(client_address(ip("127.0.0.1"), &headers, &trusted, ForwardedHeader::Forwarded), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn ipv6_and_ports() {
+        let trusted = [cidr("::1")];
+        let forwarded = fields(&[("Forwarded", "for=\"[2001:db8::7]:4711\"")]);
+        assert_eq!(client_address(ip("::1"), &forwarded, &trusted, ForwardedHeader::Forwarded), ip("2001:db8::7"));
+        let forwarded = fields(&[("Forwarded", "for=\"[2001:db8::7]\"")]);
+        assert_eq!(client_address(ip("::1"), &forwarded, &trusted, ForwardedHeader::Forwarded), ip("2001:db8::7"));
+        let xff = fields(&[("X-Forwarded-For", "2001:db8::7")]);
+        assert_eq!(client_address(ip("::1"), &xff, &trusted, ForwardedHeader::XForwardedFor), ip("2001:db8::7"));
+        let xff = fields(&[("X-Forwarded-For", "203.0.113.7:4711")]);
+        assert_eq!(client_address(ip("::1"), &xff, &trusted, ForwardedHeader::XForwardedFor), ip("203.0.113.7"));
+    }
+}
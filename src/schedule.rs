@@ -0,0 +1,256 @@
+//! The internal cron-like scheduler
+
+use crate::{
+    config::Config,
+    error,
+    error::{Error, ErrorKind},
+    minecraft,
+    time::UtcTime,
+};
+use serde::Deserialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A cron expression with the fields `minute hour day-of-month month day-of-week`, evaluated in UTC
+///
+/// # Syntax
+/// Each field is a comma-separated list of `*`, single values (`5`) or ranges (`1-5`), each with an optional step
+/// (`*/15`, `0-30/10`). Like in classic cron, if both day-of-month and day-of-week are restricted (i.e. do not start
+/// with `*`), a day matches if either field matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CronExpr {
+    /// The matching minutes as bitset
+    minutes: u64,
+    /// The matching hours as bitset
+    hours: u64,
+    /// The matching days of month as bitset
+    days: u64,
+    /// The matching months as bitset
+    months: u64,
+    /// The matching days of week as bitset
+    weekdays: u64,
+    /// Whether the day-of-month field is restricted (i.e. does not start with `*`)
+    days_restricted: bool,
+    /// Whether the day-of-week field is restricted (i.e. does not start with `*`)
+    weekdays_restricted: bool,
+}
+impl CronExpr {
+    /// Parses a cron expression
+    pub fn new(expr: &str) -> Result<Self, Error> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(error!("Cron expression must have exactly five fields: {expr}"));
+        };
+
+        // Parse the fields; day-of-week `7` is an alias for sunday
+        let weekdays_parsed = Self::parse_field(weekdays, 0, 7, expr)?;
+        let weekdays_parsed = (weekdays_parsed | (weekdays_parsed >> 7)) & 0b111_1111;
+        Ok(Self {
+            minutes: Self::parse_field(minutes, 0, 59, expr)?,
+            hours: Self::parse_field(hours, 0, 23, expr)?,
+            days: Self::parse_field(days, 1, 31, expr)?,
+            months: Self::parse_field(months, 1, 12, expr)?,
+            weekdays: weekdays_parsed,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+
+    /// Checks whether the expression matches the given point in time
+    pub fn matches(&self, time: &UtcTime) -> bool {
+        let contains = |set: u64, value: u8| set.checked_shr(u32::from(value)).is_some_and(|set| set & 1 == 1);
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => contains(self.days, time.day) || contains(self.weekdays, time.weekday),
+            _ => contains(self.days, time.day) && contains(self.weekdays, time.weekday),
+        };
+        contains(self.minutes, time.minute)
+            && contains(self.hours, time.hour)
+            && contains(self.months, time.month)
+            && day_matches
+    }
+
+    /// Parses a single cron field into a bitset
+    fn parse_field(field: &str, min: u8, max: u8, expr: &str) -> Result<u64, Error> {
+        let mut set = 0;
+        for part in field.split(',') {
+            // Split the step
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u8>().ok().filter(|step| *step > 0)),
+                None => (part, Some(1)),
+            };
+            let Some(step) = step else {
+                return Err(error!("Invalid step in cron expression: {expr}"));
+            };
+
+            // Parse the range
+            let parse = |value: &str| value.parse::<u8>().ok().filter(|value| (min..=max).contains(value));
+            let bounds = match range.split_once('-') {
+                _ if range == "*" => Some((min, max)),
+                Some((start, end)) => parse(start).zip(parse(end)),
+                None => parse(range).map(|value| (value, value)),
+            };
+            let Some((start, end)) = bounds.filter(|(start, end)| start <= end) else {
+                return Err(error!("Invalid value in cron expression: {expr}"));
+            };
+
+            // Set the matching values
+            for value in (start..=end).step_by(usize::from(step)) {
+                set |= 1u64.checked_shl(u32::from(value)).unwrap_or(0);
+            }
+        }
+        Ok(set)
+    }
+}
+impl TryFrom<String> for CronExpr {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(&value)
+    }
+}
+
+/// Spawns the scheduler thread if any schedules are configured
+///
+/// # Note
+/// The scheduler wakes up once per minute and executes every matching schedule in a separate thread. If the previous
+/// execution of a schedule is still running, the execution is skipped.
+pub fn spawn(config: &Arc<Config>) -> Result<(), Error> {
+    // Validate the schedules
+    for schedule in &config.schedules {
+        let true = config.webhooks.hooks.contains_key(&schedule.hook) else {
+            let error = error!("Schedule refers to an unknown webhook: {}", schedule.hook);
+            return Err(error.with_kind(ErrorKind::Config));
+        };
+    }
+    if config.schedules.is_empty() {
+        return Ok(());
+    }
+
+    // Spawn the scheduler
+    let config = config.clone();
+    let running: Vec<_> = config.schedules.iter().map(|_| Arc::new(AtomicBool::new(false))).collect();
+    thread::spawn(move || loop {
+        // Sleep until the start of the next minute
+        let unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let remaining = 60u64.saturating_sub(unix % 60);
+        thread::sleep(Duration::from_secs(remaining));
+
+        // Execute all matching schedules
+        let now = UtcTime::now();
+        for (schedule, running) in config.schedules.iter().zip(&running) {
+            if !schedule.cron.matches(&now) {
+                continue;
+            }
+
+            // Skip the execution if the previous one is still running
+            if running.swap(true, SeqCst) {
+                eprintln!("Skipping scheduled webhook `{}`: previous execution is still running", schedule.hook);
+                continue;
+            }
+
+            // Execute the webhook
+            let (config, running, hook) = (config.clone(), running.clone(), schedule.hook.clone());
+            thread::spawn(move || {
+                eprintln!("Executing scheduled webhook `{hook}`");
+                minecraft::trigger(&hook, &config);
+                running.store(false, SeqCst);
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CronExpr;
+    use crate::time::UtcTime;
+
+    /// Parses a cron expression
+    fn cron(expr: &str) -> CronExpr {
+        CronExpr::new(expr).expect("invalid test expression")
+    }
+
+    #[test]
+    fn fields() {
+        let expr = cron("5 4 * * *");
+        assert_eq!(expr.minutes, 1 << 5);
+        assert_eq!(expr.hours, 1 << 4);
+        assert_eq!(expr.days, 0xFFFF_FFFE);
+        assert_eq!(expr.months, 0b1_1111_1111_1110);
+        assert_eq!(expr.weekdays, 0b111_1111);
+
+        // Lists and ranges
+        let expr = cron("1,2,10-12 * * * *");
+        assert_eq!(expr.minutes, (1 << 1) | (1 << 2) | (1 << 10) | (1 << 11) | (1 << 12));
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(cron("*/15 * * * *").minutes, (1 << 0) | (1 << 15) | (1 << 30) | (1 << 45));
+        assert_eq!(cron("0-30/10 * * * *").minutes, (1 << 0) | (1 << 10) | (1 << 20) | (1 << 30));
+        assert_eq!(cron("* */12 * * *").hours, (1 << 0) | (1 << 12));
+    }
+
+    #[test]
+    fn sunday_alias() {
+        assert_eq!(cron("* * * * 7").weekdays, 1 << 0);
+        assert_eq!(cron("* * * * 0").weekdays, 1 << 0);
+        assert_eq!(cron("* * * * 5-7").weekdays, (1 << 0) | (1 << 5) | (1 << 6));
+    }
+
+    #[test]
+    fn invalid() {
+        for expr in ["* * * *", "* * * * * *", "60 * * * *", "* 24 * * *", "* * 0 * *", "* * * 13 *", "* * * * 8"] {
+            assert!(CronExpr::new(expr).is_err(), "{expr}");
+        }
+        for expr in ["*/0 * * * *", "5-1 * * * *", "x * * * *", "1- * * * *", "*/x * * * *"] {
+            assert!(CronExpr::new(expr).is_err(), "{expr}");
+        }
+    }
+
+    #[test]
+    fn matches() {
+        // 2026-10-15 16:00:00 UTC, a thursday
+        let time = UtcTime::from_unix(1_792_080_000);
+        assert!(cron("0 16 * * *").matches(&time));
+        assert!(cron("0 16 15 10 4").matches(&time));
+        assert!(!cron("1 16 * * *").matches(&time));
+        assert!(!cron("0 16 * 11 *").matches(&time));
+    }
+
+    #[test]
+    fn day_or_rule() {
+        // 2026-10-13 is a tuesday, 2026-10-15 a thursday and 2026-10-18 a sunday
+        let (tuesday_13th, thursday_15th, sunday_18th) =
+            (UtcTime::from_unix(1_791_892_800), UtcTime::from_unix(1_792_065_600), UtcTime::from_unix(1_792_324_800));
+
+        // Both fields restricted: either field matches
+        let expr = cron("0 12 13 * 0");
+        assert!(expr.matches(&tuesday_13th));
+        assert!(expr.matches(&sunday_18th));
+        assert!(!expr.matches(&thursday_15th));
+
+        // Only one field restricted: both fields must match
+        let expr = cron("0 12 13 * *");
+        assert!(expr.matches(&tuesday_13th));
+        assert!(!expr.matches(&sunday_18th));
+        let expr = cron("0 12 * * 0");
+        assert!(!expr.matches(&tuesday_13th));
+        assert!(expr.matches(&sunday_18th));
+
+        // Stepped wildcards are unrestricted, so both fields must match
+        let expr = cron("0 12 */2 * 0");
+        assert!(!expr.matches(&sunday_18th));
+        assert!(!expr.matches(&tuesday_13th));
+        let expr = cron("0 12 13 * */1");
+        assert!(expr.matches(&tuesday_13th));
+        assert!(!expr.matches(&thursday_15th));
+    }
+}
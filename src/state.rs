@@ -0,0 +1,330 @@
+//! A small persisted key-value state store for stateful webhook templates
+
+use crate::{
+    config::{Config, StateConfig},
+    error,
+    error::{Error, ErrorKind},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::PathBuf,
+    str::Chars,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The global state store if enabled
+static STORE: OnceLock<Option<Mutex<StateStore>>> = OnceLock::new();
+
+/// A persisted, size-bounded key-value store
+#[derive(Debug)]
+struct StateStore {
+    /// The path to the JSON file
+    path: PathBuf,
+    /// The stored values
+    values: BTreeMap<String, String>,
+    /// The config limits
+    config: StateConfig,
+}
+impl StateStore {
+    /// Loads the store from the given file or creates an empty store if the file does not exist
+    fn load(config: &StateConfig) -> Result<Self, Error> {
+        // Load the values
+        let path = PathBuf::from(&config.file);
+        let values = match path.exists() {
+            true => json_decode(&fs::read_to_string(&path)?)?,
+            false => BTreeMap::new(),
+        };
+
+        // Enforce the limits on the loaded values
+        let this = Self { path, values: BTreeMap::new(), config: config.clone() };
+        this.check(&values).map_err(|e| error!(with: e, "Invalid state file: {}", config.file))?;
+        Ok(Self { values, ..this })
+    }
+
+    /// Checks whether the given values can be set without exceeding the limits
+    fn check(&self, values: &BTreeMap<String, String>) -> Result<(), Error> {
+        let new_keys = values.keys().filter(|key| !self.values.contains_key(*key)).count();
+        if self.values.len().saturating_add(new_keys) > self.config.entries_max {
+            return Err(error!("State store is full ({} entries)", self.config.entries_max));
+        }
+        if let Some((key, _)) = values.iter().find(|(_, value)| value.len() > self.config.value_size_max) {
+            return Err(error!("State value for {key} is too large (limit {} bytes)", self.config.value_size_max));
+        }
+        Ok(())
+    }
+
+    /// Sets the given values and persists the store
+    fn update(&mut self, values: BTreeMap<String, String>) -> Result<(), Error> {
+        // Validate the limits before modifying anything
+        self.check(&values)?;
+
+        // Update the values and write the file atomically
+        self.values.extend(values);
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, json_encode(&self.values))?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Initializes the global state store if it is enabled
+pub fn init(config: &Config) -> Result<(), Error> {
+    let store = match &config.state {
+        Some(state) => Some(Mutex::new(StateStore::load(state).map_err(|e| e.with_kind(ErrorKind::Config))?)),
+        None => None,
+    };
+    let _ = STORE.set(store);
+    Ok(())
+}
+
+/// Whether a template references the state store
+pub fn is_stateful(template: &str) -> bool {
+    template.contains("{state:")
+}
+
+/// Renders a template by replacing `{state:<key>}` with the stored value (or an empty string if the key is unset) and
+/// `{timestamp}` with the current unix timestamp
+///
+/// # Note
+/// If the state store is disabled, templates are returned unchanged, so that existing commands are not affected.
+pub fn render(template: &str) -> Result<String, Error> {
+    // Only render placeholders if the store is enabled
+    let store = STORE.get().and_then(Option::as_ref);
+    let guard = match (is_stateful(template), store) {
+        (true, Some(store)) => Some(store.lock().unwrap_or_else(PoisonError::into_inner)),
+        (true, None) => return Err(error!("Template references the state store, but the state store is disabled")),
+        (false, Some(_)) => None,
+        (false, None) => return Ok(template.to_string()),
+    };
+
+    // Replace the placeholders
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        // Copy everything before the placeholder
+        let (before, placeholder) = rest.split_at(start);
+        rendered.push_str(before);
+
+        // Find the end of the placeholder; skip the brace if it is not a placeholder at all
+        let end = placeholder.find('}').map(|end| end.saturating_add(1)).unwrap_or(placeholder.len());
+        let nested = placeholder.get(1..end).is_some_and(|inner| inner.contains('{'));
+        let end = if nested { 1 } else { end };
+
+        // Resolve the placeholder
+        let (placeholder, remaining) = placeholder.split_at(end);
+        match placeholder.strip_prefix("{state:").and_then(|key| key.strip_suffix('}')) {
+            Some(key) => {
+                let value = guard.as_ref().and_then(|store| store.values.get(key));
+                rendered.push_str(value.map(String::as_str).unwrap_or_default());
+            }
+            None if placeholder == "{timestamp}" => {
+                let unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                let _ = write!(rendered, "{unix}");
+            }
+            None => rendered.push_str(placeholder),
+        }
+        rest = remaining;
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Renders the given values and checks them against the store limits, so that a webhook can fail before its command is
+/// executed
+pub fn prepare(set_state: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>, Error> {
+    // Render the values before locking the store
+    let mut values = BTreeMap::new();
+    for (key, template) in set_state {
+        values.insert(key.clone(), render(template)?);
+    }
+
+    // Check the limits
+    let Some(store) = STORE.get().and_then(Option::as_ref) else {
+        return Err(error!("Webhook sets state, but the state store is disabled"));
+    };
+    store.lock().unwrap_or_else(PoisonError::into_inner).check(&values)?;
+    Ok(values)
+}
+
+/// Stores the given prepared values
+pub fn update(values: BTreeMap<String, String>) -> Result<(), Error> {
+    let Some(store) = STORE.get().and_then(Option::as_ref) else {
+        return Err(error!("Webhook sets state, but the state store is disabled"));
+    };
+    store.lock().unwrap_or_else(PoisonError::into_inner).update(values)
+}
+
+/// Encodes a string as JSON string
+pub fn json_string(string: &str, json: &mut String) {
+    json.push('"');
+    for char_ in string.chars() {
+        match char_ {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            char_ if char_.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(char_));
+            }
+            char_ => json.push(char_),
+        }
+    }
+    json.push('"');
+}
+
+/// Encodes a flat string map as JSON object
+fn json_encode(values: &BTreeMap<String, String>) -> String {
+    // Encode the object
+    let mut json = String::from("{");
+    for (index, (key, value)) in values.iter().enumerate() {
+        json.push_str(if index == 0 { "\n    " } else { ",\n    " });
+        json_string(key, &mut json);
+        json.push_str(": ");
+        json_string(value, &mut json);
+    }
+    json.push_str("\n}\n");
+    json
+}
+
+/// Decodes a flat JSON object with string values
+fn json_decode(json: &str) -> Result<BTreeMap<String, String>, Error> {
+    /// Skips whitespace and returns the next character
+    fn next_token(chars: &mut Chars) -> Option<char> {
+        chars.find(|char_| !char_.is_whitespace())
+    }
+
+    /// Decodes a 4-digit hex UTF-16 code unit
+    fn decode_code_unit(chars: &mut Chars) -> Option<u16> {
+        let hex: String = chars.take(4).collect();
+        u16::from_str_radix(&hex, 16).ok()
+    }
+
+    /// Decodes a JSON string after the opening quote
+    fn decode_string(chars: &mut Chars) -> Option<String> {
+        let mut string = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(string),
+                '\\' => match chars.next()? {
+                    'n' => string.push('\n'),
+                    'r' => string.push('\r'),
+                    't' => string.push('\t'),
+                    'b' => string.push('\u{8}'),
+                    'f' => string.push('\u{c}'),
+                    'u' => {
+                        // Decode the code unit and a trailing low surrogate if necessary
+                        let high = decode_code_unit(chars)?;
+                        let decoded = match (0xD800..0xDC00).contains(&high) {
+                            true => {
+                                let (Some('\\'), Some('u')) = (chars.next(), chars.next()) else {
+                                    return None;
+                                };
+                                char::decode_utf16([high, decode_code_unit(chars)?]).next()?.ok()?
+                            }
+                            false => char::decode_utf16([high]).next()?.ok()?,
+                        };
+                        string.push(decoded);
+                    }
+                    escaped => string.push(escaped),
+                },
+                char_ => string.push(char_),
+            }
+        }
+    }
+
+    // Decode the object
+    let invalid = || error!("Invalid state file (expected a flat JSON object with string values)");
+    let mut chars = json.chars();
+    let mut values = BTreeMap::new();
+    let Some('{') = next_token(&mut chars) else {
+        return Err(invalid());
+    };
+    loop {
+        // Decode the key or the end of the object
+        match next_token(&mut chars) {
+            Some('}') if values.is_empty() => break,
+            Some('"') => (),
+            _ => return Err(invalid()),
+        }
+        let key = decode_string(&mut chars).ok_or_else(invalid)?;
+
+        // Decode the value
+        let (Some(':'), Some('"')) = (next_token(&mut chars), next_token(&mut chars)) else {
+            return Err(invalid());
+        };
+        let value = decode_string(&mut chars).ok_or_else(invalid)?;
+        values.insert(key, value);
+
+        // Continue or end the object
+        match next_token(&mut chars) {
+            Some(',') => continue,
+            Some('}') => break,
+            _ => return Err(invalid()),
+        }
+    }
+
+    // Ensure there is no trailing garbage
+    let None = next_token(&mut chars) else {
+        return Err(invalid());
+    };
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_decode, json_encode};
+    use std::collections::BTreeMap;
+
+    /// Creates a string map
+    fn map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let cases = [
+            map(&[]),
+            map(&[("key", "value")]),
+            map(&[("a", ""), ("b", "1700000000"), ("c", "x")]),
+            map(&[("quote\"backslash\\", "line\nbreak\r\ttab")]),
+            map(&[("control", "\u{0}\u{1}\u{1f}\u{7f}"), ("unicode", "äöü ✓ 🎉")]),
+        ];
+        for values in cases {
+            let json = json_encode(&values);
+            assert_eq!(json_decode(&json).expect("failed to decode encoded state"), values, "{json}");
+        }
+    }
+
+    #[test]
+    fn decode_escapes() {
+        let json = r#"{"a": "ä🎉\/\b\f", "b" : "\"\\"}"#;
+        let expected = map(&[("a", "ä🎉/\u{8}\u{c}"), ("b", "\"\\")]);
+        assert_eq!(json_decode(json).expect("failed to decode state"), expected);
+        assert_eq!(json_decode(" { } ").expect("failed to decode state"), map(&[]));
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let cases = [
+            "",
+            "[]",
+            "{",
+            "{,}",
+            r#"{"a"}"#,
+            r#"{"a": 1}"#,
+            r#"{"a": "b",}"#,
+            r#"{"a": "b"} x"#,
+            r#"{"a": "b"#,
+            r#"{"a": "\ud83c"}"#,
+            r#"{"a": "\udf89"}"#,
+            r#"{"a": "\u00g0"}"#,
+        ];
+        for json in cases {
+            assert!(json_decode(json).is_err(), "{json}");
+        }
+    }
+}
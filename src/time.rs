@@ -0,0 +1,105 @@
+//! Minimal UTC calendar time helpers
+
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A broken-down UTC timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcTime {
+    /// The year
+    pub year: i64,
+    /// The month (`1..=12`)
+    pub month: u8,
+    /// The day of the month (`1..=31`)
+    pub day: u8,
+    /// The hour (`0..=23`)
+    pub hour: u8,
+    /// The minute (`0..=59`)
+    pub minute: u8,
+    /// The second (`0..=59`)
+    pub second: u8,
+    /// The day of the week (`0..=6` where `0` is sunday)
+    pub weekday: u8,
+}
+impl UtcTime {
+    /// The month abbreviations
+    const MONTHS: [&'static str; 12] =
+        ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    /// The current time
+    pub fn now() -> Self {
+        let unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        Self::from_unix(unix)
+    }
+
+    /// Creates a broken-down timestamp from the given unix timestamp
+    #[allow(clippy::arithmetic_side_effects, reason = "All values are bounded by u64::MAX / 86400 days")]
+    #[allow(clippy::cast_possible_truncation, reason = "All truncated values are bounded by their modulus")]
+    pub const fn from_unix(unix: u64) -> Self {
+        // Split into days and seconds of the day
+        let days = (unix / 86_400) as i64;
+        let seconds = unix % 86_400;
+
+        // Convert the days into a civil date (see http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+        let shifted = days + 719_468;
+        let era = shifted.div_euclid(146_097);
+        let day_of_era = shifted.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_shifted = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_shifted + 2) / 5 + 1;
+        let month = if month_shifted < 10 { month_shifted + 3 } else { month_shifted - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+            weekday: (days + 4).rem_euclid(7) as u8,
+        }
+    }
+}
+impl Display for UtcTime {
+    /// Formats the timestamp in the common log format (e.g. `10/Oct/2000:13:55:36 +0000`)
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let month = usize::from(self.month).checked_sub(1).and_then(|index| Self::MONTHS.get(index));
+        let month = month.copied().unwrap_or("???");
+        let Self { year, day, hour, minute, second, .. } = self;
+        write!(f, "{day:02}/{month}/{year:04}:{hour:02}:{minute:02}:{second:02} +0000")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UtcTime;
+
+    #[test]
+    fn from_unix() {
+        // The unix epoch was a thursday
+        let epoch = UtcTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0, weekday: 4 };
+        assert_eq!(UtcTime::from_unix(0), epoch);
+
+        // A leap day
+        let leap_day = UtcTime { year: 2000, month: 2, day: 29, hour: 0, minute: 0, second: 0, weekday: 2 };
+        assert_eq!(UtcTime::from_unix(951_782_400), leap_day);
+
+        // The last second of a year
+        let new_years_eve = UtcTime { year: 2025, month: 12, day: 31, hour: 23, minute: 59, second: 59, weekday: 3 };
+        assert_eq!(UtcTime::from_unix(1_767_225_599), new_years_eve);
+
+        // 2100 is not a leap year
+        let century = UtcTime { year: 2100, month: 1, day: 1, hour: 0, minute: 0, second: 0, weekday: 5 };
+        assert_eq!(UtcTime::from_unix(4_102_444_800), century);
+        assert_eq!(UtcTime::from_unix(4_102_444_800 - 86_400 * 306).month, 3);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(UtcTime::from_unix(1_792_080_000).to_string(), "15/Oct/2026:16:00:00 +0000");
+    }
+}
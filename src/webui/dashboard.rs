@@ -0,0 +1,128 @@
+//! The admin dashboard
+
+use crate::{
+    accesslog::Usage,
+    cache::Cache,
+    config::{Config, DashboardConfig},
+    minecraft,
+    time::UtcTime,
+};
+use ehttpd::http::{Request, RequestExt, Response, ResponseExt};
+use sha2::{Digest, Sha512_256};
+use std::{
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The dashboard template
+const TEMPLATE: &str = include_str!("dashboard.html");
+
+/// The status of a single target
+#[derive(Debug, Clone)]
+struct TargetStatus {
+    /// The status command output or the error message
+    result: Result<String, String>,
+    /// The round-trip time of the status command
+    latency: Duration,
+    /// The point in time when the status has been checked
+    checked: UtcTime,
+}
+
+/// Escapes a string for HTML
+fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for char_ in string.chars() {
+        match char_ {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            char_ => escaped.push(char_),
+        }
+    }
+    escaped
+}
+
+/// Checks whether the request carries the dashboard bearer token
+fn is_authorized(request: &Request, dashboard: &DashboardConfig) -> bool {
+    // Get the token
+    let Some(authorization) = request.field("Authorization") else {
+        return false;
+    };
+    let Some(token) = authorization.strip_prefix(b"Bearer ") else {
+        return false;
+    };
+
+    // Compare the hashes to avoid leaking the token via timing side channels
+    let token = Sha512_256::digest(token);
+    let expected = Sha512_256::digest(dashboard.token.as_bytes());
+    token == expected
+}
+
+/// Gets the status of the RCON target, either from cache or by executing the status command
+fn target_status(config: &Config, dashboard: &DashboardConfig, usage: &mut Usage) -> TargetStatus {
+    /// The cached target states
+    static CACHE: OnceLock<Mutex<Cache<String, TargetStatus>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| {
+        // Create the cache
+        let ttl = Duration::from_secs(dashboard.cache_ttl);
+        Mutex::new(Cache::new(&config.cache, ttl))
+    });
+
+    // Use the cached status if possible
+    let target = &config.rcon.address;
+    if let Some(status) = cache.lock().unwrap_or_else(PoisonError::into_inner).get(target) {
+        return status.clone();
+    }
+
+    // Execute the status command
+    let start = Instant::now();
+    let result = minecraft::exec(config, &dashboard.command).map_err(|e| e.to_string());
+    let status = TargetStatus { result, latency: start.elapsed(), checked: UtcTime::now() };
+    usage.rcon = usage.rcon.saturating_add(status.latency);
+
+    // Cache the status
+    cache.lock().unwrap_or_else(PoisonError::into_inner).insert(target.clone(), status.clone());
+    status
+}
+
+/// Serves the admin dashboard
+pub fn dashboard(request: &Request, config: &Config, usage: &mut Usage) -> Response {
+    // Ensure the dashboard is enabled
+    let Some(dashboard) = &config.dashboard else {
+        // Return 404
+        let mut response: Response = ResponseExt::new_404_notfound();
+        response.set_content_length(0);
+        return response;
+    };
+
+    // Authorize the request
+    if !is_authorized(request, dashboard) {
+        // Log unauthorized access and return 401
+        eprintln!("Unauthorized dashboard access");
+        let mut response: Response = ResponseExt::new_401_unauthorized("Bearer");
+        response.set_content_length(0);
+        return response;
+    }
+
+    // Render the status row
+    let status = target_status(config, dashboard, usage);
+    let (health, output) = match &status.result {
+        Ok(output) => ("<span class=\"online\">online</span>", output.as_str()),
+        Err(error) => ("<span class=\"offline\">offline</span>", error.as_str()),
+    };
+    let row = format!(
+        "<tr><td>{}</td><td>{health}</td><td>{}ms</td><td><pre>{}</pre></td><td>{}</td></tr>",
+        escape(&config.rcon.address),
+        status.latency.as_millis(),
+        escape(output.trim()),
+        status.checked
+    );
+
+    // Create 200 OK response
+    let mut response: Response = ResponseExt::new_200_ok();
+    response.set_field("Content-Type", "text/html; charset=utf-8");
+    response.set_body_data(TEMPLATE.replace("{{rows}}", &row));
+    response
+}
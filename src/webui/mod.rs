@@ -1,5 +1,7 @@
 //! The web-UI site
 
+pub mod dashboard;
+
 use ehttpd::http::{Request, Response, ResponseExt};
 
 /// The website data